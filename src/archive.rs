@@ -0,0 +1,353 @@
+//! Portable stream archives, as written by `s2 export` and read by `s2 import`.
+//!
+//! An archive is laid out as:
+//!
+//! ```text
+//! magic | records | manifest | manifest_len | magic
+//! ```
+//!
+//! where `magic` is the 8 bytes `S2ARCHV1`, `records` are frames in the
+//! [binary record format](crate::record_format::RecordFormat::Binary),
+//! `manifest` is JSON describing the stream and the records section, and
+//! `manifest_len` is its length as a big-endian `u64`.
+
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use colored::Colorize;
+use futures::{StreamExt, TryStreamExt, future};
+use s2_sdk::{
+    S2,
+    types::{ReadFrom, ReadInput, ReadLimits, ReadStart, ReadStop},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+    select,
+};
+use xxhash_rust::xxh3::Xxh3Default;
+
+use crate::{
+    cli::{CreateStreamArgs, ExportArgs, ImportArgs},
+    error::CliError,
+    ops,
+    record_format::{BinaryFormatter, RecordWriter},
+    types::StreamConfig,
+};
+
+const MAGIC: &[u8; 8] = b"S2ARCHV1";
+const VERSION: u32 = 1;
+const TRAILER_LEN: u64 = 16;
+
+/// Description of an archive and the stream it was exported from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// URI of the exported stream.
+    pub stream: String,
+    /// When the export started, in RFC 3339 format.
+    pub exported_at: String,
+    pub config: StreamConfig,
+    /// Number of records in the archive.
+    pub records: u64,
+    pub first_seq_num: Option<u64>,
+    pub last_seq_num: Option<u64>,
+    /// Length in bytes of the records section.
+    pub records_len: u64,
+    /// Hex-encoded XXH3-64 checksum of the records section.
+    pub checksum: String,
+}
+
+/// Outcome of an import.
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    /// URI of the stream the archive was exported from.
+    pub source: String,
+    /// Records appended by this run.
+    pub records: u64,
+    /// Number of records in the archive.
+    pub archive_records: u64,
+    /// Whether the import was interrupted, leaving the stream partially imported.
+    pub aborted: bool,
+}
+
+fn invalid(msg: impl ToString) -> CliError {
+    CliError::InvalidArchive(msg.to_string())
+}
+
+fn encode_trailer(manifest: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(manifest.len() + TRAILER_LEN as usize);
+    buf.extend_from_slice(manifest);
+    buf.extend_from_slice(&(manifest.len() as u64).to_be_bytes());
+    buf.extend_from_slice(MAGIC);
+    buf
+}
+
+/// Length of the manifest preceding a trailer, if the trailer is valid.
+fn decode_trailer(trailer: &[u8; TRAILER_LEN as usize]) -> Option<u64> {
+    let (len, magic) = trailer.split_at(8);
+    (magic == MAGIC).then(|| u64::from_be_bytes(len.try_into().expect("8 bytes")))
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Export a stream to an archive.
+///
+/// Returns `None` if interrupted, in which case no archive is written.
+pub async fn export(s2: &S2, args: ExportArgs) -> Result<Option<Manifest>, CliError> {
+    let exported_at = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+    let config = ops::get_stream_config(s2, args.uri.clone()).await?;
+    let tail = ops::check_tail(s2, args.uri.clone()).await?.seq_num;
+
+    let write_error = |e: io::Error| CliError::RecordWrite(format!("{}: {e}", args.out.display()));
+    let tmp = tmp_path(&args.out);
+    let mut writer = BufWriter::new(File::create(&tmp).await.map_err(write_error)?);
+    writer.write_all(MAGIC).await.map_err(write_error)?;
+
+    let mut manifest = Manifest {
+        version: VERSION,
+        stream: args.uri.to_string(),
+        exported_at,
+        config: config.into(),
+        records: 0,
+        first_seq_num: None,
+        last_seq_num: None,
+        records_len: 0,
+        checksum: String::new(),
+    };
+
+    if tail > 0 {
+        let batches = ops::read_session(
+            s2,
+            args.uri.clone(),
+            ReadInput::new()
+                .with_start(ReadStart::new().with_from(ReadFrom::SeqNum(0)))
+                .with_stop(ReadStop::new().with_limits(ReadLimits::new().with_count(tail as usize)))
+                .with_ignore_command_records(true),
+        )
+        .await?;
+        let records = ops::records(batches)
+            .try_take_while(|record| future::ready(Ok(record.seq_num < tail)))
+            .try_filter(|record| future::ready(!record.is_command_record()));
+        let mut records = std::pin::pin!(records);
+
+        let mut hasher = Xxh3Default::new();
+        let mut frame = Vec::new();
+        loop {
+            select! {
+                record = records.next() => {
+                    let Some(record) = record.transpose()? else {
+                        break;
+                    };
+                    frame.clear();
+                    BinaryFormatter
+                        .write_record(&record, &mut frame)
+                        .await
+                        .map_err(write_error)?;
+                    writer.write_all(&frame).await.map_err(write_error)?;
+                    hasher.update(&frame);
+
+                    manifest.records += 1;
+                    manifest.records_len += frame.len() as u64;
+                    manifest.first_seq_num.get_or_insert(record.seq_num);
+                    manifest.last_seq_num = Some(record.seq_num);
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    drop(writer);
+                    _ = tokio::fs::remove_file(&tmp).await;
+                    eprintln!("{}", "■ [ABORTED]".red().bold());
+                    return Ok(None);
+                }
+            }
+        }
+        manifest.checksum = format!("{:016x}", hasher.digest());
+    } else {
+        manifest.checksum = format!("{:016x}", Xxh3Default::new().digest());
+    }
+
+    let manifest_json = serde_json::to_vec(&manifest)?;
+    writer
+        .write_all(&encode_trailer(&manifest_json))
+        .await
+        .map_err(write_error)?;
+    writer.flush().await.map_err(write_error)?;
+    writer.get_ref().sync_all().await.map_err(write_error)?;
+    drop(writer);
+    tokio::fs::rename(&tmp, &args.out)
+        .await
+        .map_err(write_error)?;
+
+    Ok(Some(manifest))
+}
+
+/// Read the manifest of an archive, leaving `file` at an unspecified position.
+async fn read_manifest(file: &mut File) -> Result<Manifest, CliError> {
+    let len = file.metadata().await.map_err(invalid)?.len();
+    if len < MAGIC.len() as u64 + TRAILER_LEN {
+        return Err(invalid("file is too short"));
+    }
+
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic).await.map_err(invalid)?;
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))
+        .await
+        .map_err(invalid)?;
+    file.read_exact(&mut trailer).await.map_err(invalid)?;
+    let manifest_len = match decode_trailer(&trailer) {
+        Some(manifest_len) if &magic == MAGIC => manifest_len,
+        _ => return Err(invalid("not an S2 archive")),
+    };
+
+    let records_end = (len - TRAILER_LEN)
+        .checked_sub(manifest_len)
+        .filter(|end| *end >= MAGIC.len() as u64)
+        .ok_or_else(|| invalid("manifest length exceeds file size"))?;
+    let mut manifest = vec![0u8; manifest_len as usize];
+    file.seek(SeekFrom::Start(records_end))
+        .await
+        .map_err(invalid)?;
+    file.read_exact(&mut manifest).await.map_err(invalid)?;
+    let manifest: Manifest =
+        serde_json::from_slice(&manifest).map_err(|e| invalid(format!("manifest: {e}")))?;
+
+    if manifest.version != VERSION {
+        return Err(invalid(format!("unsupported version {}", manifest.version)));
+    }
+    if records_end - MAGIC.len() as u64 != manifest.records_len {
+        return Err(invalid("records section length does not match manifest"));
+    }
+    Ok(manifest)
+}
+
+/// Verify the checksum of the records section against the manifest.
+async fn verify_records(file: &mut File, manifest: &Manifest) -> Result<(), CliError> {
+    file.seek(SeekFrom::Start(MAGIC.len() as u64))
+        .await
+        .map_err(invalid)?;
+    let mut reader = BufReader::new(file).take(manifest.records_len);
+    let mut hasher = Xxh3Default::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await.map_err(invalid)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let checksum = format!("{:016x}", hasher.digest());
+    if checksum != manifest.checksum {
+        return Err(invalid(format!(
+            "checksum mismatch: expected {}, computed {checksum}",
+            manifest.checksum
+        )));
+    }
+    Ok(())
+}
+
+pub async fn import(s2: &S2, args: ImportArgs) -> Result<ImportSummary, CliError> {
+    let mut file = File::open(&args.archive).await.map_err(invalid)?;
+    let manifest = read_manifest(&mut file).await?;
+    verify_records(&mut file, &manifest).await?;
+
+    ops::create_stream(
+        s2,
+        CreateStreamArgs {
+            uri: args.uri.clone(),
+            config: manifest.config.clone(),
+        },
+    )
+    .await?;
+    eprintln!(
+        "{}",
+        format!("✓ [CREATED] {} from {}", args.uri, manifest.stream)
+            .green()
+            .bold()
+    );
+
+    file.seek(SeekFrom::Start(MAGIC.len() as u64))
+        .await
+        .map_err(invalid)?;
+    let reader = BufReader::new(file).take(manifest.records_len);
+    let records = BinaryFormatter::parse_records(reader)
+        .map_err(|e| CliError::RecordReaderInit(e.to_string()));
+
+    let acks = ops::append(
+        s2,
+        Box::pin(records),
+        args.uri.clone(),
+        None,
+        Some(0),
+        *args.linger,
+    );
+    let mut acks = std::pin::pin!(acks);
+    let mut imported = 0;
+    let mut aborted = false;
+    let mut last_printed_batch_end: Option<u64> = None;
+
+    loop {
+        select! {
+            ack = acks.next() => {
+                match ack {
+                    Some(Ok(ack)) => {
+                        imported += 1;
+                        if last_printed_batch_end.is_none_or(|end| end != ack.batch.end.seq_num) {
+                            last_printed_batch_end = Some(ack.batch.end.seq_num);
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "✓ [IMPORTED] {}..{} of {} records",
+                                    ack.batch.start.seq_num, ack.batch.end.seq_num, manifest.records
+                                )
+                                .green()
+                                .bold()
+                            );
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => break,
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                aborted = true;
+                break;
+            }
+        }
+    }
+
+    Ok(ImportSummary {
+        source: manifest.stream,
+        records: imported,
+        archive_records: manifest.records,
+        aborted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAGIC, TRAILER_LEN, decode_trailer, encode_trailer};
+
+    #[test]
+    fn test_trailer_roundtrip() {
+        let manifest = br#"{"version":1}"#;
+        let encoded = encode_trailer(manifest);
+        assert_eq!(&encoded[..manifest.len()], manifest);
+
+        let trailer: [u8; TRAILER_LEN as usize] = encoded[manifest.len()..].try_into().unwrap();
+        assert_eq!(decode_trailer(&trailer), Some(manifest.len() as u64));
+
+        let mut corrupt = trailer;
+        corrupt[15] ^= 1;
+        assert_eq!(decode_trailer(&corrupt), None);
+        assert_eq!(&trailer[8..], MAGIC);
+    }
+}
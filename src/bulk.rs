@@ -0,0 +1,204 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use colored::Colorize;
+use futures::{Future, StreamExt, TryStreamExt, future, stream};
+use s2_sdk::{S2, types::BasinName};
+use serde::Serialize;
+
+use crate::{
+    cli::{BulkArgs, ListStreamsArgs},
+    error::CliError,
+    ops,
+    output::{self, OutputFormat},
+    types::{
+        Glob, S2BasinAndMaybeStreamUri, S2BasinAndStreamSelector, S2BasinAndStreamUri,
+        StreamSelector, serialize_display,
+    },
+};
+
+/// Result of an operation on one of the streams matching a pattern.
+#[derive(Debug, Serialize)]
+pub struct StreamOutcome {
+    #[serde(serialize_with = "serialize_display")]
+    pub uri: S2BasinAndStreamUri,
+    pub error: Option<String>,
+}
+
+/// List the live streams in `basin` whose names match `glob`.
+async fn resolve(
+    s2: &S2,
+    basin: &BasinName,
+    glob: &Glob,
+) -> Result<Vec<S2BasinAndStreamUri>, CliError> {
+    let prefix = match glob.prefix() {
+        "" => None,
+        prefix => Some(prefix.parse().map_err(|e| {
+            CliError::InvalidArgs(miette::miette!("invalid stream name prefix: {e}"))
+        })?),
+    };
+    let streams = ops::list_streams(
+        s2,
+        ListStreamsArgs {
+            uri: S2BasinAndMaybeStreamUri {
+                basin: basin.clone(),
+                stream: None,
+            },
+            prefix,
+            start_after: None,
+            limit: None,
+            no_auto_paginate: false,
+        },
+    )
+    .await?;
+    streams
+        .try_filter(|info| {
+            future::ready(info.deleted_at.is_none() && glob.matches(info.name.as_ref()))
+        })
+        .map_ok(|info| S2BasinAndStreamUri {
+            basin: basin.clone(),
+            stream: info.name,
+        })
+        .try_collect()
+        .await
+}
+
+/// Resolve each selector to the streams it matches, in order and without duplicates.
+///
+/// Fails if no streams match.
+pub async fn resolve_all(
+    s2: &S2,
+    selectors: &[S2BasinAndStreamSelector],
+) -> Result<Vec<S2BasinAndStreamUri>, CliError> {
+    let mut uris: Vec<S2BasinAndStreamUri> = Vec::new();
+    for selector in selectors {
+        let matched = match &selector.stream {
+            StreamSelector::Exact(_) => selector.exact().into_iter().collect(),
+            StreamSelector::Glob(glob) => resolve(s2, &selector.basin, glob).await?,
+        };
+        for uri in matched {
+            if !uris.contains(&uri) {
+                uris.push(uri);
+            }
+        }
+    }
+    if uris.is_empty() {
+        return Err(CliError::InvalidArgs(miette::miette!(
+            "No streams match {}",
+            selectors
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    Ok(uris)
+}
+
+/// Show the matching streams and ask whether to `action` them.
+///
+/// Returns `false` if the user declines.
+fn confirm(action: &str, uris: &[S2BasinAndStreamUri], args: &BulkArgs) -> Result<bool, CliError> {
+    eprintln!(
+        "{}",
+        format!("⦿ {} {} streams:", action, uris.len())
+            .blue()
+            .bold()
+    );
+    for uri in uris {
+        eprintln!("  {uri}");
+    }
+    if args.yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(CliError::InvalidArgs(miette::miette!(
+            "Refusing to {} {} streams without confirmation. Pass --yes to skip the prompt.",
+            action.to_lowercase(),
+            uris.len()
+        )));
+    }
+
+    eprint!("Proceed? [y/N] ");
+    std::io::stderr().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| CliError::InvalidArgs(miette::miette!("{e}")))?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Resolve the streams selected by `selector` and confirm the `action` with the user.
+///
+/// Returns `None` if no streams match or the user declines.
+pub async fn prepare(
+    s2: &S2,
+    selector: &S2BasinAndStreamSelector,
+    action: &str,
+    args: &BulkArgs,
+) -> Result<Option<Vec<S2BasinAndStreamUri>>, CliError> {
+    let StreamSelector::Glob(glob) = &selector.stream else {
+        return Ok(selector.exact().map(|uri| vec![uri]));
+    };
+    let uris = resolve(s2, &selector.basin, glob).await?;
+    if uris.is_empty() {
+        eprintln!(
+            "{}",
+            format!("✓ No streams match {selector}").green().bold()
+        );
+        return Ok(None);
+    }
+    if !confirm(action, &uris, args)? {
+        eprintln!("{}", "■ [ABORTED]".red().bold());
+        return Ok(None);
+    }
+    Ok(Some(uris))
+}
+
+/// Run `op` on each stream with bounded concurrency, reporting each outcome as it completes.
+///
+/// Fails if the operation failed on any stream.
+pub async fn run<F, Fut, T>(
+    uris: Vec<S2BasinAndStreamUri>,
+    args: &BulkArgs,
+    output: OutputFormat,
+    op: F,
+) -> Result<(), CliError>
+where
+    F: Fn(S2BasinAndStreamUri) -> Fut,
+    Fut: Future<Output = Result<T, CliError>>,
+{
+    let total = uris.len();
+    let outcomes: Vec<StreamOutcome> = stream::iter(uris)
+        .map(|uri| {
+            let fut = op(uri.clone());
+            async move {
+                let error = fut.await.err().map(|e| e.to_string());
+                match &error {
+                    None => eprintln!("{}", format!("✓ {uri}").green()),
+                    Some(e) => eprintln!("{}", format!("✗ {uri}: {e}").red()),
+                }
+                StreamOutcome { uri, error }
+            }
+        })
+        .buffer_unordered(args.parallelism.get())
+        .collect()
+        .await;
+
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    if output.is_structured() {
+        output::print_value(output, &outcomes)?;
+    } else {
+        let summary = format!("{} succeeded, {failed} failed", total - failed);
+        if failed == 0 {
+            eprintln!("{}", format!("✓ [DONE] {summary}").green().bold());
+        } else {
+            eprintln!("{}", format!("✗ [DONE] {summary}").red().bold());
+        }
+    }
+
+    if failed > 0 {
+        return Err(CliError::BulkOperation(failed, total));
+    }
+    Ok(())
+}
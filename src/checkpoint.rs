@@ -0,0 +1,258 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{error::CliError, types::S2BasinAndStreamUri};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    stream: String,
+    #[serde(flatten)]
+    state: T,
+}
+
+/// JSON state persisted for a stream, replaced atomically on every save.
+#[derive(Debug)]
+struct StateFile {
+    path: PathBuf,
+    stream: String,
+}
+
+impl StateFile {
+    fn new(path: impl Into<PathBuf>, uri: &S2BasinAndStreamUri) -> Self {
+        Self {
+            path: path.into(),
+            stream: uri.to_string(),
+        }
+    }
+
+    fn error(&self, msg: impl ToString) -> CliError {
+        CliError::Checkpoint(self.path.clone(), msg.to_string())
+    }
+
+    fn load<T: DeserializeOwned>(&self) -> Result<Option<T>, CliError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(self.error(e)),
+        };
+        let envelope: Envelope<T> = serde_json::from_str(&contents).map_err(|e| self.error(e))?;
+        if envelope.stream != self.stream {
+            return Err(self.error(format!(
+                "belongs to {}, not {}",
+                envelope.stream, self.stream
+            )));
+        }
+        Ok(Some(envelope.state))
+    }
+
+    fn save<T: Serialize>(&self, state: &T) -> Result<(), CliError> {
+        let envelope = Envelope {
+            stream: self.stream.clone(),
+            state,
+        };
+        let contents = serde_json::to_vec(&envelope).map_err(|e| self.error(e))?;
+        write_atomic(&self.path, &contents).map_err(|e| self.error(e))
+    }
+}
+
+/// Write to a temporary file next to `path`, then rename it into place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReadProgress {
+    seq_num: u64,
+}
+
+/// Last sequence number written by a `read` consumer, persisted to a file.
+#[derive(Debug)]
+pub struct Checkpoint(StateFile);
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>, uri: &S2BasinAndStreamUri) -> Self {
+        Self(StateFile::new(path, uri))
+    }
+
+    /// The checkpointed sequence number, if the file exists.
+    pub fn load(&self) -> Result<Option<u64>, CliError> {
+        Ok(self.0.load::<ReadProgress>()?.map(|p| p.seq_num))
+    }
+
+    /// Atomically replace the checkpoint with `seq_num`.
+    pub fn save(&self, seq_num: u64) -> Result<(), CliError> {
+        self.0.save(&ReadProgress { seq_num })
+    }
+}
+
+/// Position in the input up to which an append has been acknowledged, and the
+/// sequence number assigned to the record starting at that position.
+///
+/// The input is a file, where the position is a byte offset, or a source stream
+/// when copying, where it is the next sequence number to read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct AppendProgress {
+    input: String,
+    offset: u64,
+    seq_num: u64,
+}
+
+/// End offsets of submitted but unacknowledged records, in submission order.
+#[derive(Debug, Clone, Default)]
+pub struct PendingOffsets(Arc<Mutex<VecDeque<u64>>>);
+
+impl PendingOffsets {
+    pub fn push(&self, offset: u64) {
+        self.0.lock().expect("not poisoned").push_back(offset);
+    }
+}
+
+/// Progress of an append from a file or stream, persisted so it can resume after a failure.
+#[derive(Debug)]
+pub struct ResumeState {
+    file: StateFile,
+    progress: AppendProgress,
+    /// Records already appended after the saved progress, when the previous
+    /// run stopped between an acknowledgement and saving it.
+    skip: u64,
+    pending: PendingOffsets,
+}
+
+impl ResumeState {
+    /// Load the saved progress for `input`, reconciled with the current stream tail.
+    pub fn load(
+        path: impl Into<PathBuf>,
+        uri: &S2BasinAndStreamUri,
+        input: &str,
+        tail: u64,
+    ) -> Result<Self, CliError> {
+        let file = StateFile::new(path, uri);
+        let progress = match file.load::<AppendProgress>()? {
+            Some(progress) if progress.input != input => {
+                return Err(file.error(format!("tracks input {}, not {input}", progress.input)));
+            }
+            Some(progress) if progress.seq_num > tail => {
+                return Err(file.error(format!(
+                    "expects the stream tail to be at least {}, but it is {tail}",
+                    progress.seq_num
+                )));
+            }
+            Some(progress) => progress,
+            None => AppendProgress {
+                input: input.to_owned(),
+                offset: 0,
+                seq_num: tail,
+            },
+        };
+        Ok(Self {
+            skip: tail - progress.seq_num,
+            file,
+            progress,
+            pending: PendingOffsets::default(),
+        })
+    }
+
+    /// Input position to continue reading from.
+    pub fn offset(&self) -> u64 {
+        self.progress.offset
+    }
+
+    /// Sequence number of the last saved progress.
+    pub fn seq_num(&self) -> u64 {
+        self.progress.seq_num
+    }
+
+    /// Number of records after [`Self::offset`] that were already appended.
+    pub fn skip(&self) -> u64 {
+        self.skip
+    }
+
+    /// Sequence number the first submitted record must be assigned.
+    pub fn match_seq_num(&self) -> u64 {
+        self.progress.seq_num + self.skip
+    }
+
+    /// Where to record the end offset of each submitted record.
+    pub fn pending(&self) -> PendingOffsets {
+        self.pending.clone()
+    }
+
+    /// Record that all records before `end_seq_num` are durable, and save the progress.
+    pub fn acknowledged(&mut self, end_seq_num: u64) -> Result<(), CliError> {
+        let next_seq_num = self.match_seq_num();
+        let Some(count) = end_seq_num.checked_sub(next_seq_num).filter(|n| *n > 0) else {
+            return Ok(());
+        };
+        let offset = {
+            let mut pending = self.pending.0.lock().expect("not poisoned");
+            let count = (count as usize).min(pending.len());
+            pending.drain(..count).next_back()
+        };
+        if let Some(offset) = offset {
+            self.progress.offset = offset;
+            self.progress.seq_num = end_seq_num;
+            self.skip = 0;
+            self.file.save(&self.progress)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, ResumeState};
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("checkpoint");
+        let uri = "s2://my-basin-1/events".parse().unwrap();
+        let checkpoint = Checkpoint::new(&path, &uri);
+
+        assert_eq!(checkpoint.load().unwrap(), None);
+        checkpoint.save(41).unwrap();
+        checkpoint.save(42).unwrap();
+        assert_eq!(checkpoint.load().unwrap(), Some(42));
+
+        let other = "s2://my-basin-1/other".parse().unwrap();
+        assert!(Checkpoint::new(&path, &other).load().is_err());
+    }
+
+    #[test]
+    fn test_resume_state() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state");
+        let uri = "s2://my-basin-1/events".parse().unwrap();
+        let input = "input.txt";
+
+        let mut state = ResumeState::load(&path, &uri, input, 10).unwrap();
+        assert_eq!((state.offset(), state.match_seq_num()), (0, 10));
+        let pending = state.pending();
+        for offset in [5, 12, 20] {
+            pending.push(offset);
+        }
+        state.acknowledged(12).unwrap();
+
+        // The last record was appended but its acknowledgement was not saved.
+        let state = ResumeState::load(&path, &uri, input, 13).unwrap();
+        assert_eq!(state.offset(), 12);
+        assert_eq!((state.skip(), state.match_seq_num()), (1, 13));
+
+        assert!(ResumeState::load(&path, &uri, input, 11).is_err());
+        assert!(ResumeState::load(&path, &uri, "other.txt", 13).is_err());
+    }
+}
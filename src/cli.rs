@@ -1,18 +1,31 @@
 use clap::{Args, Parser, Subcommand, builder::styling};
+use http::HeaderValue;
 use s2_sdk::types::{
     AccessTokenId, AccessTokenIdPrefix, AccessTokenIdStartAfter, BasinNamePrefix,
     BasinNameStartAfter, FencingToken, StreamNamePrefix, StreamNameStartAfter,
 };
-use std::num::NonZeroU64;
+use std::{
+    net::SocketAddr,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+};
 
+use crate::exec::ExecMode;
+use crate::filter::HeaderFilter;
+use crate::lock::parse_lease_ttl;
+use crate::output::OutputFormat;
+use crate::plan::ManifestFormat;
 use crate::record_format::{
-    RecordFormat, RecordsIn, RecordsOut, parse_records_input_source, parse_records_output_source,
+    CsvColumn, RecordDelimiter, RecordFormat, RecordsIn, RecordsOut, TemplateFormatter,
+    parse_records_input_source, parse_records_output_source,
 };
+use crate::shard::ShardKey;
 use crate::types::{
     AccessTokenMatcher, BasinConfig, BasinMatcher, Interval, Operation, PermittedOperationGroups,
-    S2BasinAndMaybeStreamUri, S2BasinAndStreamUri, S2BasinUri, StorageClass, StreamConfig,
-    StreamMatcher,
+    S2BasinAndMaybeStreamUri, S2BasinAndStreamSelector, S2BasinAndStreamUri, S2BasinUri,
+    StorageClass, StreamConfig, StreamMatcher,
 };
+use crate::webhook::WebhookHeader;
 
 const STYLES: styling::Styles = styling::Styles::styled()
     .header(styling::AnsiColor::Green.on_default().bold())
@@ -30,10 +43,24 @@ const GENERAL_USAGE: &str = color_print::cstr!(
 #[derive(Parser, Debug)]
 #[command(name = "s2", version, override_usage = GENERAL_USAGE, styles = STYLES)]
 pub struct Cli {
+    /// Configuration profile to use.
+    ///
+    /// Defaults to the active profile set with `s2 config switch-profile`.
+    #[arg(long, global = true, env = "S2_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Output format for command results.
+    ///
+    /// Records from `read` and `tail` are formatted according to `--format`.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+// Parsed once, so variant sizes do not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Manage CLI configuration.
@@ -96,11 +123,15 @@ pub enum Command {
     /// Create a stream.
     CreateStream(CreateStreamArgs),
 
-    /// Delete a stream.
+    /// Delete a stream, or all streams matching a glob pattern.
     DeleteStream {
         /// S2 URI of the format: s2://{basin}/{stream}
+        /// The stream can be a glob pattern such as `tmp/*`.
         #[arg(value_name = "S2_URI")]
-        uri: S2BasinAndStreamUri,
+        uri: S2BasinAndStreamSelector,
+
+        #[command(flatten)]
+        bulk: BulkArgs,
     },
 
     /// Get stream config.
@@ -110,7 +141,7 @@ pub enum Command {
         uri: S2BasinAndStreamUri,
     },
 
-    /// Reconfigure a stream.
+    /// Reconfigure a stream, or all streams matching a glob pattern.
     ReconfigureStream(ReconfigureStreamArgs),
 
     /// Check the tail position of a stream.
@@ -123,7 +154,7 @@ pub enum Command {
         uri: S2BasinAndStreamUri,
     },
 
-    /// Set a trim point for a stream.
+    /// Set a trim point for a stream, or all streams matching a glob pattern.
     ///
     /// Trimming is eventually consistent, and trimmed records may be visible
     /// for a brief period.
@@ -150,6 +181,80 @@ pub enum Command {
     /// Tail a stream, showing the last N records.
     Tail(TailArgs),
 
+    /// Copy records from one stream to another.
+    ///
+    /// Copies up to the tail of the source at the time the copy starts.
+    /// Command records are not copied.
+    Cp(CpArgs),
+
+    /// Continuously mirror records from one stream to another.
+    ///
+    /// The destination is fenced on startup so that only the latest mirror
+    /// instance can append to it. Each mirrored record carries its source
+    /// sequence number in the `s2-mirror-seq-num` header, which is used to
+    /// resume after a restart. Command records are not mirrored.
+    /// Transient errors are retried with backoff, resuming from the last
+    /// mirrored record.
+    Mirror(MirrorArgs),
+
+    /// Summarize the records in a range of a stream, without dumping them.
+    ///
+    /// Reports record count, metered bytes, size distribution, header names,
+    /// timestamp gaps and records per second over time.
+    /// Reads up to the tail at the time the command starts.
+    Stats(StatsArgs),
+
+    /// Export a stream to a portable archive file.
+    ///
+    /// The archive holds the stream configuration and all records up to the
+    /// tail at the time the export starts, along with a checksum.
+    /// Command records are not exported.
+    Export(ExportArgs),
+
+    /// Import a stream from an archive file written by `export`.
+    ///
+    /// Creates the stream with the archived configuration,
+    /// then appends the archived records with their original timestamps.
+    Import(ImportArgs),
+
+    /// Show the changes needed for basins and streams to match a manifest.
+    ///
+    /// The manifest is a TOML file of basins and streams with their configs,
+    /// e.g. `[basins.my-basin.streams.events]` with `retention_policy = "7d"`.
+    /// Stream config fields left out of the manifest are not managed.
+    Plan(ManifestArgs),
+
+    /// Apply the changes needed for basins and streams to match a manifest.
+    Apply(ManifestArgs),
+
+    /// Print the config of a basin and its streams as a manifest.
+    ///
+    /// The output is ordered by name, so snapshots can be diffed,
+    /// and can be passed to `plan` and `apply`.
+    DumpConfig(DumpConfigArgs),
+
+    /// Hold a lease on a stream, for mutual exclusion between processes.
+    ///
+    /// The stream is fenced with a fresh token on acquisition, and lease
+    /// records are appended to it on every acquire, renew and release.
+    /// A lease that is not renewed before its TTL elapses can be taken over.
+    #[command(subcommand)]
+    Lock(LockCommand),
+
+    /// Serve an HTTP API to streams, for tools that cannot use an S2 SDK.
+    ///
+    /// `POST /basins/{basin}/streams/{stream}` appends newline-delimited JSON
+    /// records in the format of `append --format json`, and responds with the ack.
+    /// `GET` on the same path streams records as Server-Sent Events, from the
+    /// `seq_num`, `timestamp` or `tail_offset` query parameter, or else the tail,
+    /// until `count` records if given. Both accept `format=json-base64`, and
+    /// appends also accept `fencing_token` and `match_seq_num`.
+    /// Appends must be sent with a `Content-Type` of `application/json` or
+    /// `application/x-ndjson`, and requests must name the listen address in `Host`.
+    /// Requests are made with the CLI's credentials, so listening on a non-loopback
+    /// address requires `--token`.
+    Serve(ServeArgs),
+
     /// Benchmark a stream to measure throughput and latency.
     Bench(BenchArgs),
 }
@@ -175,6 +280,23 @@ pub enum ConfigCommand {
         /// Config key
         key: crate::config::ConfigKey,
     },
+    /// List configuration profiles.
+    ListProfiles,
+    /// Create a new, empty configuration profile.
+    CreateProfile {
+        /// Profile name
+        name: String,
+    },
+    /// Switch the active configuration profile.
+    SwitchProfile {
+        /// Profile name
+        name: String,
+    },
+    /// Delete a configuration profile.
+    DeleteProfile {
+        /// Profile name
+        name: String,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -351,23 +473,43 @@ pub struct CreateStreamArgs {
 #[derive(Args, Debug)]
 pub struct ReconfigureStreamArgs {
     /// S2 URI of the format: s2://{basin}/{stream}
+    /// The stream can be a glob pattern such as `tmp/*`.
     #[arg(value_name = "S2_URI")]
-    pub uri: S2BasinAndStreamUri,
+    pub uri: S2BasinAndStreamSelector,
 
     #[clap(flatten)]
     pub config: StreamConfig,
+
+    #[command(flatten)]
+    pub bulk: BulkArgs,
 }
 
 #[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("trim_to").required(true)))]
 pub struct TrimArgs {
     /// S2 URI of the format: s2://{basin}/{stream}
+    /// The stream can be a glob pattern such as `tmp/*`.
     #[arg(value_name = "S2_URI")]
-    pub uri: S2BasinAndStreamUri,
+    pub uri: S2BasinAndStreamSelector,
 
     /// Earliest sequence number that should be retained.
     /// This sequence number is only allowed to advance,
     /// and any regression will be ignored.
-    pub trim_point: u64,
+    #[arg(group = "trim_to")]
+    pub trim_point: Option<u64>,
+
+    /// Trim records with a timestamp before this one,
+    /// in milliseconds since Unix epoch.
+    #[arg(long, group = "trim_to")]
+    pub before_timestamp: Option<u64>,
+
+    /// Trim records older than a human-friendly age e.g. "7d".
+    #[arg(long, group = "trim_to")]
+    pub older_than: Option<humantime::Duration>,
+
+    /// Trim all but the last N records.
+    #[arg(long, group = "trim_to")]
+    pub keep_last: Option<u64>,
 
     /// Enforce fencing token.
     #[arg(short = 'f', long)]
@@ -376,6 +518,21 @@ pub struct TrimArgs {
     /// Enforce that the sequence number issued to the first record matches.
     #[arg(short = 'm', long)]
     pub match_seq_num: Option<u64>,
+
+    #[command(flatten)]
+    pub bulk: BulkArgs,
+}
+
+/// Options for commands that accept a glob pattern of streams.
+#[derive(Args, Debug, Clone)]
+pub struct BulkArgs {
+    /// Maximum number of matching streams to operate on concurrently.
+    #[arg(long, default_value = "16")]
+    pub parallelism: NonZeroUsize,
+
+    /// Skip the confirmation prompt for a glob pattern.
+    #[arg(short = 'y', long, default_value_t = false)]
+    pub yes: bool,
 }
 
 #[derive(Args, Debug)]
@@ -415,21 +572,52 @@ pub struct AppendArgs {
     #[arg(long, value_enum, default_value_t)]
     pub format: RecordFormat,
 
-    /// Input newline delimited records to append from a file or stdin.
+    /// Input delimited records to append from a file or stdin.
     /// Use "-" to read from stdin.
     #[arg(short = 'i', long, value_parser = parse_records_input_source, default_value = "-")]
     pub input: RecordsIn,
 
+    /// Delimiter between input records, as a byte string supporting the
+    /// escapes `\0`, `\n`, `\r`, `\t`, `\\` and `\xNN`.
+    /// A value starting with `^` is instead a regular expression matching the
+    /// first line of each record, e.g. "^\d{4}-" for timestamped log entries.
+    /// Ignored for the binary format.
+    #[arg(short = 'd', long, default_value = "\\n")]
+    pub delimiter: RecordDelimiter,
+
     /// How long to wait for more records before flushing a batch.
     #[arg(long, default_value = "5ms")]
     pub linger: humantime::Duration,
+
+    /// Track acknowledged progress through the input file in this state file,
+    /// and resume from it on restart without appending duplicates.
+    /// Assumes no other writers append to the stream in the meantime.
+    #[arg(long, conflicts_with = "match_seq_num")]
+    pub resume_state: Option<PathBuf>,
+
+    /// Shard records across the streams named by a `{N..M}` or `{a,b,...}`
+    /// pattern in the URI, by a stable hash of each record's key: either
+    /// "header:NAME" for the value of a header, or "body:FIELD" for a field of
+    /// a JSON body, where FIELD may be a dotted path.
+    /// Quote the URI so the shell does not expand the pattern.
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["match_seq_num", "resume_state"])]
+    pub shard_by: Option<ShardKey>,
+
+    /// Append command records from the input, such as fencing tokens and trims.
+    /// By default they are skipped, so piping `read` into `append` does not
+    /// replay them on the destination.
+    #[arg(long, default_value_t = false)]
+    pub append_commands: bool,
 }
 
 #[derive(Args, Debug)]
 pub struct ReadArgs {
-    /// S2 URI of the format: s2://{basin}/{stream}
-    #[arg(value_name = "S2_URI")]
-    pub uri: S2BasinAndStreamUri,
+    /// S2 URIs of the format: s2://{basin}/{stream}, where the stream may be
+    /// a glob pattern such as `events/*`.
+    /// Records from multiple streams are merged in timestamp order,
+    /// and the start position and limits apply to each stream.
+    #[arg(value_name = "S2_URI", required = true)]
+    pub uris: Vec<S2BasinAndStreamSelector>,
 
     /// Starting sequence number (inclusive).
     #[arg(short = 's', long, group = "start")]
@@ -470,17 +658,61 @@ pub struct ReadArgs {
     #[arg(long, value_enum, default_value_t)]
     pub format: RecordFormat,
 
+    /// Columns to output when using the CSV format.
+    /// Each column is one of `seq_num`, `timestamp`, `body`, or a header name.
+    #[arg(long, value_delimiter = ',', default_value = "seq_num,timestamp,body")]
+    pub csv_columns: Vec<CsvColumn>,
+
+    /// Render each record with a template instead of an output format,
+    /// e.g. "{seq_num}\\t{timestamp:rfc3339}\\t{header.user}\\t{body}".
+    /// Fields are `seq_num`, `timestamp` (`:rfc3339` for a date), `body` and
+    /// `header.<name>`. Body and headers accept the encodings `:utf8` (default),
+    /// `:escape`, `:json`, `:hex` and `:base64`. Use `{{` and `}}` for literal braces.
+    #[arg(long, conflicts_with = "format")]
+    pub template: Option<TemplateFormatter>,
+
+    /// Delimiter written after each record, in the same syntax as for `append`.
+    /// Record start patterns write records separated by newlines.
+    /// Ignored for the binary format.
+    #[arg(short = 'd', long, default_value = "\\n")]
+    pub delimiter: RecordDelimiter,
+
     /// Output records to a file or stdout.
     /// Use "-" to write to stdout.
     #[arg(short = 'o', long, value_parser = parse_records_output_source, default_value = "-")]
     pub output: RecordsOut,
+
+    /// Persist the last written sequence number to this file after each batch,
+    /// and resume after it on restart.
+    /// The start position options only apply when the file does not exist yet.
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// When merging multiple followed streams, how long a stream without new
+    /// records holds back records from the others.
+    #[arg(long, default_value = "1s")]
+    pub watermark_delay: humantime::Duration,
+
+    #[command(flatten)]
+    pub commands: CommandRecordArgs,
+
+    #[command(flatten)]
+    pub filter: RecordFilterArgs,
+
+    #[command(flatten)]
+    pub exec: ExecArgs,
+
+    #[command(flatten)]
+    pub webhook: WebhookArgs,
 }
 
 #[derive(Args, Debug)]
 pub struct TailArgs {
-    /// S2 URI of the format: s2://{basin}/{stream}
-    #[arg(value_name = "S2_URI")]
-    pub uri: S2BasinAndStreamUri,
+    /// S2 URIs of the format: s2://{basin}/{stream}, where the stream may be
+    /// a glob pattern such as `events/*`.
+    /// Records from multiple streams are merged in timestamp order.
+    #[arg(value_name = "S2_URI", required = true)]
+    pub uris: Vec<S2BasinAndStreamSelector>,
 
     /// Output the last N records instead of the default (10).
     #[arg(short = 'n', long = "lines", default_value_t = 10)]
@@ -494,10 +726,370 @@ pub struct TailArgs {
     #[arg(long, value_enum, default_value_t)]
     pub format: RecordFormat,
 
+    /// Columns to output when using the CSV format.
+    /// Each column is one of `seq_num`, `timestamp`, `body`, or a header name.
+    #[arg(long, value_delimiter = ',', default_value = "seq_num,timestamp,body")]
+    pub csv_columns: Vec<CsvColumn>,
+
+    /// Render each record with a template instead of an output format,
+    /// e.g. "{seq_num}\\t{timestamp:rfc3339}\\t{header.user}\\t{body}".
+    /// Fields are `seq_num`, `timestamp` (`:rfc3339` for a date), `body` and
+    /// `header.<name>`. Body and headers accept the encodings `:utf8` (default),
+    /// `:escape`, `:json`, `:hex` and `:base64`. Use `{{` and `}}` for literal braces.
+    #[arg(long, conflicts_with = "format")]
+    pub template: Option<TemplateFormatter>,
+
     /// Output records to a file or stdout.
     /// Use "-" to write to stdout.
     #[arg(short = 'o', long, value_parser = parse_records_output_source, default_value = "-")]
     pub output: RecordsOut,
+
+    /// When merging multiple followed streams, how long a stream without new
+    /// records holds back records from the others.
+    #[arg(long, default_value = "1s")]
+    pub watermark_delay: humantime::Duration,
+
+    #[command(flatten)]
+    pub commands: CommandRecordArgs,
+
+    #[command(flatten)]
+    pub filter: RecordFilterArgs,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct RecordFilterArgs {
+    /// Only output records with a header matching `name=glob` or `name~=regex`.
+    /// Can be repeated, in which case all must match.
+    #[arg(long = "header-filter", value_name = "FILTER")]
+    pub header_filters: Vec<HeaderFilter>,
+
+    /// Only output records with a body matching a regex.
+    #[arg(long, value_name = "REGEX")]
+    pub body_grep: Option<regex::bytes::Regex>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct CommandRecordArgs {
+    /// Leave out command records, such as fencing tokens and trims.
+    #[arg(long, default_value_t = false, conflicts_with = "only_commands")]
+    pub skip_commands: bool,
+
+    /// Only output command records.
+    #[arg(long, default_value_t = false)]
+    pub only_commands: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExecArgs {
+    /// Handle records with the command given after `--` instead of writing them
+    /// to the output. Records are passed on its stdin in the output format, and
+    /// the checkpoint only advances past records it handled successfully.
+    #[arg(long, default_value_t = false, requires = "command")]
+    pub exec: bool,
+
+    /// How to run the command.
+    #[arg(long, value_enum, default_value_t, requires = "exec")]
+    pub exec_mode: ExecMode,
+
+    /// Attempts to handle a record or batch before giving up on it.
+    #[arg(long, default_value = "3", requires = "exec")]
+    pub exec_attempts: NonZeroU32,
+
+    /// Delay before retrying a failed record or batch, doubling with each attempt.
+    #[arg(long, default_value = "1s", requires = "exec")]
+    pub exec_backoff: humantime::Duration,
+
+    /// Append records the command gave up on to this file, in the `json-base64`
+    /// format, and carry on. Without it, reading stops at the first such record.
+    #[arg(long, requires = "exec")]
+    pub dead_letter: Option<PathBuf>,
+
+    /// Command to handle records, after `--`.
+    #[arg(last = true, value_name = "COMMAND", requires = "exec")]
+    pub command: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct WebhookArgs {
+    /// POST records to this URL instead of writing them to the output, as a JSON
+    /// array per batch. Requires `--format json` or `--format json-base64`.
+    /// The checkpoint only advances past delivered batches.
+    #[arg(long, value_name = "URL", conflicts_with = "exec")]
+    pub webhook: Option<reqwest::Url>,
+
+    /// Header to add to webhook requests, as "name: value".
+    #[arg(long = "webhook-header", value_name = "HEADER", requires = "webhook")]
+    pub webhook_headers: Vec<WebhookHeader>,
+
+    /// Batches to deliver at once. Requests may then arrive out of order, but
+    /// the checkpoint only advances past batches once all earlier ones were delivered.
+    #[arg(long, default_value = "1", requires = "webhook")]
+    pub webhook_concurrency: NonZeroUsize,
+
+    /// Attempts to deliver a batch, retrying on connection errors and
+    /// 429 or 5xx responses.
+    #[arg(long, default_value = "5", requires = "webhook")]
+    pub webhook_attempts: NonZeroU32,
+
+    /// Delay before retrying a delivery, doubling with each attempt.
+    #[arg(long, default_value = "1s", requires = "webhook")]
+    pub webhook_backoff: humantime::Duration,
+
+    /// Timeout for each webhook request.
+    #[arg(long, default_value = "30s", requires = "webhook")]
+    pub webhook_timeout: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8787")]
+    pub listen: SocketAddr,
+
+    /// Allow browser pages from this origin to make requests, or "*" for any.
+    #[arg(long)]
+    pub allow_origin: Option<HeaderValue>,
+
+    /// Require requests to send this token as `Authorization: Bearer <token>`.
+    #[arg(long, env = "S2_SERVE_TOKEN")]
+    pub token: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// Starting sequence number (inclusive).
+    /// Defaults to the start of the stream.
+    #[arg(short = 's', long, group = "start")]
+    pub seq_num: Option<u64>,
+
+    /// Starting timestamp in milliseconds since Unix epoch (inclusive).
+    #[arg(long, group = "start")]
+    pub timestamp: Option<u64>,
+
+    /// Starting timestamp as a human-friendly delta from current time e.g. "1h",
+    /// which will be converted to milliseconds since Unix epoch.
+    #[arg(long, group = "start")]
+    pub ago: Option<humantime::Duration>,
+
+    /// Start from N records before the tail of the stream.
+    #[arg(long, group = "start")]
+    pub tail_offset: Option<u64>,
+
+    /// Limit the number of records scanned.
+    #[arg(short = 'n', long)]
+    pub count: Option<u64>,
+
+    /// Exclusive end-timestamp in milliseconds since Unix epoch.
+    #[arg(long)]
+    pub until: Option<u64>,
+
+    /// Width of the buckets for records per second over time.
+    #[arg(long, default_value = "1m")]
+    pub interval: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct CpArgs {
+    /// Source S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "SRC_URI")]
+    pub src: S2BasinAndStreamUri,
+
+    /// Destination S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "DST_URI")]
+    pub dst: S2BasinAndStreamUri,
+
+    /// Starting sequence number in the source (inclusive).
+    #[arg(short = 's', long)]
+    pub seq_num: Option<u64>,
+
+    /// Ending sequence number in the source (exclusive).
+    /// Defaults to the tail of the source.
+    #[arg(short = 'e', long)]
+    pub end_seq_num: Option<u64>,
+
+    /// Keep the timestamps of source records,
+    /// instead of letting the destination assign them.
+    #[arg(long, default_value_t = false)]
+    pub preserve_timestamps: bool,
+
+    /// Enforce fencing token on the destination.
+    #[arg(short = 'f', long)]
+    pub fencing_token: Option<FencingToken>,
+
+    /// Fence the destination with a new token before copying,
+    /// and enforce it for the rest of the copy.
+    /// A resumed copy only enforces the token.
+    #[arg(long)]
+    pub fence: Option<FencingToken>,
+
+    /// Track progress in this state file, and resume from it on restart
+    /// without copying duplicates.
+    /// Assumes no other writers append to the destination in the meantime.
+    #[arg(long)]
+    pub resume_state: Option<PathBuf>,
+
+    /// How long to wait for more records before flushing a batch.
+    #[arg(long, default_value = "5ms")]
+    pub linger: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct MirrorArgs {
+    /// Source S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "SRC_URI")]
+    pub src: S2BasinAndStreamUri,
+
+    /// Destination S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "DST_URI")]
+    pub dst: S2BasinAndStreamUri,
+
+    /// Starting sequence number in the source (inclusive),
+    /// used when the destination has no mirrored records yet.
+    #[arg(short = 's', long)]
+    pub seq_num: Option<u64>,
+
+    /// Keep the timestamps of source records,
+    /// instead of letting the destination assign them.
+    #[arg(long, default_value_t = false)]
+    pub preserve_timestamps: bool,
+
+    /// How long to wait for more records before flushing a batch.
+    #[arg(long, default_value = "5ms")]
+    pub linger: humantime::Duration,
+
+    /// Consecutive attempts after transient errors before giving up.
+    #[arg(long, default_value = "10")]
+    pub retry_attempts: NonZeroU32,
+
+    /// Delay before retrying after a transient error, doubling with each attempt.
+    #[arg(long, default_value = "1s")]
+    pub retry_backoff: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// Path of the archive file to write.
+    #[arg(short = 'o', long)]
+    pub out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Path of the archive file to read.
+    pub archive: PathBuf,
+
+    /// S2 URI of the stream to create, of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// How long to wait for more records before flushing a batch.
+    #[arg(long, default_value = "5ms")]
+    pub linger: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct ManifestArgs {
+    /// Path of the manifest file.
+    #[arg(short = 'f', long)]
+    pub file: PathBuf,
+
+    /// Delete streams in managed basins that are not in the manifest.
+    #[arg(long, default_value_t = false)]
+    pub prune: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DumpConfigArgs {
+    /// Name of the basin to dump.
+    pub basin: S2BasinUri,
+
+    /// Format of the manifest.
+    #[arg(long, value_enum, default_value_t)]
+    pub format: ManifestFormat,
+}
+
+#[derive(Subcommand, Debug)]
+#[command(disable_help_subcommand = true)]
+pub enum LockCommand {
+    /// Acquire a lease, printing its token.
+    Acquire(LockAcquireArgs),
+
+    /// Extend a lease held with a token.
+    Renew(LockRenewArgs),
+
+    /// Release a lease held with a token.
+    Release(LockReleaseArgs),
+
+    /// Run a command while holding a lease.
+    ///
+    /// The lease is renewed every third of its TTL while the command runs, and
+    /// released when it exits. If the lease is lost, the command is killed.
+    /// The lease token is available to the command as `S2_LOCK_TOKEN`.
+    Run(LockRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct LockAcquireArgs {
+    /// S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// Name identifying the holder of the lease.
+    #[arg(long)]
+    pub holder: String,
+
+    /// Duration after which the lease can be taken over unless renewed.
+    /// Must be at least 1s.
+    #[arg(long, default_value = "30s", value_parser = parse_lease_ttl)]
+    pub ttl: humantime::Duration,
+
+    /// Wait for the lease to be released or expire, instead of failing.
+    #[arg(long, default_value_t = false)]
+    pub wait: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LockRenewArgs {
+    /// S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// Token of the held lease.
+    #[arg(long)]
+    pub token: FencingToken,
+
+    /// New duration of the lease, from now. Must be at least 1s.
+    #[arg(long, default_value = "30s", value_parser = parse_lease_ttl)]
+    pub ttl: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct LockReleaseArgs {
+    /// S2 URI of the format: s2://{basin}/{stream}
+    #[arg(value_name = "S2_URI")]
+    pub uri: S2BasinAndStreamUri,
+
+    /// Token of the held lease.
+    #[arg(long)]
+    pub token: FencingToken,
+}
+
+#[derive(Args, Debug)]
+pub struct LockRunArgs {
+    #[command(flatten)]
+    pub lock: LockAcquireArgs,
+
+    /// Command to run, and its arguments.
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
 }
 
 #[derive(Args, Debug)]
@@ -1,6 +1,6 @@
-use std::{path::PathBuf, time::Duration};
+use std::{collections::BTreeMap, path::PathBuf, process::Command, time::Duration};
 
-use config::{Config, FileFormat};
+use config::Config;
 use s2_sdk::{
     self as sdk,
     types::{AccountEndpoint, BasinEndpoint, S2Config, S2Endpoints},
@@ -26,10 +26,11 @@ impl From<Compression> for sdk::types::Compression {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct CliConfig {
     pub access_token: Option<String>,
+    pub access_token_command: Option<String>,
     pub account_endpoint: Option<String>,
     pub basin_endpoint: Option<String>,
     pub compression: Option<Compression>,
@@ -53,28 +54,73 @@ fn config_path() -> Result<PathBuf, CliConfigError> {
     Ok(path)
 }
 
-pub fn load_config_file() -> Result<CliConfig, CliConfigError> {
-    let path = config_path()?;
-    if !path.exists() {
-        return Ok(CliConfig::default());
+/// Name of the profile backed by the top-level keys of the config file.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// On-disk layout of the config file.
+///
+/// Top-level keys make up the `default` profile, and named profiles live in
+/// `[profiles.<name>]` tables.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub active_profile: Option<String>,
+    #[serde(flatten)]
+    pub default: CliConfig,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, CliConfig>,
+}
+
+impl ConfigFile {
+    /// Resolve the profile to use, preferring an explicit selection over the
+    /// active profile recorded in the file.
+    pub fn resolve_profile<'a>(&'a self, profile: Option<&'a str>) -> &'a str {
+        profile
+            .or(self.active_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(DEFAULT_PROFILE).chain(self.profiles.keys().map(String::as_str))
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&CliConfig, CliConfigError> {
+        if name == DEFAULT_PROFILE {
+            return Ok(&self.default);
+        }
+        self.profiles
+            .get(name)
+            .ok_or_else(|| CliConfigError::ProfileNotFound(name.to_owned()))
+    }
+
+    pub fn profile_mut(&mut self, name: &str) -> Result<&mut CliConfig, CliConfigError> {
+        if name == DEFAULT_PROFILE {
+            return Ok(&mut self.default);
+        }
+        self.profiles
+            .get_mut(name)
+            .ok_or_else(|| CliConfigError::ProfileNotFound(name.to_owned()))
     }
-    let builder = Config::builder().add_source(config::File::new(
-        path.to_str().expect("config path is valid utf8"),
-        FileFormat::Toml,
-    ));
-    Ok(builder.build()?.try_deserialize::<CliConfig>()?)
 }
 
-pub fn load_cli_config() -> Result<CliConfig, CliConfigError> {
+pub fn load_config_file() -> Result<ConfigFile, CliConfigError> {
     let path = config_path()?;
-    let mut builder = Config::builder();
-    if path.exists() {
-        builder = builder.add_source(config::File::new(
-            path.to_str().expect("config path is valid utf8"),
-            FileFormat::Toml,
-        ));
+    if !path.exists() {
+        return Ok(ConfigFile::default());
     }
-    builder = builder.add_source(config::Environment::with_prefix("S2"));
+    let contents = std::fs::read_to_string(&path).map_err(CliConfigError::Read)?;
+    toml::from_str(&contents).map_err(CliConfigError::Parse)
+}
+
+/// Load the effective config for a profile, with `S2_*` environment variables
+/// taking precedence over values from the config file.
+pub fn load_cli_config(profile: Option<&str>) -> Result<CliConfig, CliConfigError> {
+    let file = load_config_file()?;
+    let profile = file.resolve_profile(profile);
+    let config = file.profile(profile)?;
+    let builder = Config::builder()
+        .add_source(Config::try_from(config)?)
+        .add_source(config::Environment::with_prefix("S2"));
     Ok(builder.build()?.try_deserialize::<CliConfig>()?)
 }
 
@@ -85,6 +131,8 @@ pub fn load_cli_config() -> Result<CliConfig, CliConfigError> {
 #[strum(serialize_all = "snake_case")]
 pub enum ConfigKey {
     AccessToken,
+    /// Command whose stdout is used as the access token.
+    AccessTokenCommand,
     AccountEndpoint,
     BasinEndpoint,
     Compression,
@@ -95,6 +143,7 @@ impl CliConfig {
     pub fn get(&self, key: ConfigKey) -> Option<String> {
         match key {
             ConfigKey::AccessToken => self.access_token.clone(),
+            ConfigKey::AccessTokenCommand => self.access_token_command.clone(),
             ConfigKey::AccountEndpoint => self.account_endpoint.clone(),
             ConfigKey::BasinEndpoint => self.basin_endpoint.clone(),
             ConfigKey::Compression => self.compression.map(|c| c.to_string()),
@@ -105,6 +154,7 @@ impl CliConfig {
     pub fn set(&mut self, key: ConfigKey, value: String) -> Result<(), CliConfigError> {
         match key {
             ConfigKey::AccessToken => self.access_token = Some(value),
+            ConfigKey::AccessTokenCommand => self.access_token_command = Some(value),
             ConfigKey::AccountEndpoint => self.account_endpoint = Some(value),
             ConfigKey::BasinEndpoint => self.basin_endpoint = Some(value),
             ConfigKey::Compression => {
@@ -128,6 +178,7 @@ impl CliConfig {
     pub fn unset(&mut self, key: ConfigKey) {
         match key {
             ConfigKey::AccessToken => self.access_token = None,
+            ConfigKey::AccessTokenCommand => self.access_token_command = None,
             ConfigKey::AccountEndpoint => self.account_endpoint = None,
             ConfigKey::BasinEndpoint => self.basin_endpoint = None,
             ConfigKey::Compression => self.compression = None,
@@ -136,7 +187,7 @@ impl CliConfig {
     }
 }
 
-pub fn save_cli_config(config: &CliConfig) -> Result<PathBuf, CliConfigError> {
+pub fn save_config_file(config: &ConfigFile) -> Result<PathBuf, CliConfigError> {
     let path = config_path()?;
 
     if let Some(parent) = path.parent() {
@@ -149,30 +200,70 @@ pub fn save_cli_config(config: &CliConfig) -> Result<PathBuf, CliConfigError> {
     Ok(path)
 }
 
-pub fn set_config_value(key: ConfigKey, value: String) -> Result<PathBuf, CliConfigError> {
-    let mut config = load_config_file().unwrap_or_default();
-    config.set(key, value)?;
-    save_cli_config(&config)
+pub fn set_config_value(
+    profile: Option<&str>,
+    key: ConfigKey,
+    value: String,
+) -> Result<PathBuf, CliConfigError> {
+    let mut file = load_config_file()?;
+    let profile = file.resolve_profile(profile).to_owned();
+    file.profile_mut(&profile)?.set(key, value)?;
+    save_config_file(&file)
+}
+
+pub fn unset_config_value(
+    profile: Option<&str>,
+    key: ConfigKey,
+) -> Result<PathBuf, CliConfigError> {
+    let mut file = load_config_file()?;
+    let profile = file.resolve_profile(profile).to_owned();
+    file.profile_mut(&profile)?.unset(key);
+    save_config_file(&file)
+}
+
+pub fn create_profile(name: &str) -> Result<PathBuf, CliConfigError> {
+    let mut file = load_config_file()?;
+    if name == DEFAULT_PROFILE || file.profiles.contains_key(name) {
+        return Err(CliConfigError::ProfileExists(name.to_owned()));
+    }
+    file.profiles.insert(name.to_owned(), CliConfig::default());
+    save_config_file(&file)
 }
 
-pub fn unset_config_value(key: ConfigKey) -> Result<PathBuf, CliConfigError> {
-    let mut config = load_config_file().unwrap_or_default();
-    config.unset(key);
-    save_cli_config(&config)
+pub fn switch_profile(name: &str) -> Result<PathBuf, CliConfigError> {
+    let mut file = load_config_file()?;
+    file.profile(name)?;
+    file.active_profile = (name != DEFAULT_PROFILE).then(|| name.to_owned());
+    save_config_file(&file)
+}
+
+pub fn delete_profile(name: &str) -> Result<PathBuf, CliConfigError> {
+    let mut file = load_config_file()?;
+    if name == DEFAULT_PROFILE {
+        return Err(CliConfigError::DefaultProfileDelete);
+    }
+    if file.profiles.remove(name).is_none() {
+        return Err(CliConfigError::ProfileNotFound(name.to_owned()));
+    }
+    if file.active_profile.as_deref() == Some(name) {
+        file.active_profile = None;
+    }
+    save_config_file(&file)
 }
 
 pub fn sdk_config(config: &CliConfig) -> Result<S2Config, CliError> {
-    let access_token = config
-        .access_token
-        .as_ref()
-        .ok_or(CliConfigError::MissingAccessToken)?;
+    let access_token = match (&config.access_token, &config.access_token_command) {
+        (Some(access_token), _) => access_token.clone(),
+        (None, Some(command)) => access_token_from_command(command)?,
+        (None, None) => return Err(CliConfigError::MissingAccessToken.into()),
+    };
 
     let compression: sdk::types::Compression = config
         .compression
         .map(Into::into)
         .unwrap_or(sdk::types::Compression::None);
 
-    let mut sdk_config = S2Config::new(access_token)
+    let mut sdk_config = S2Config::new(&access_token)
         .with_user_agent("s2-cli")
         .expect("valid user agent")
         .with_request_timeout(Duration::from_secs(30))
@@ -210,3 +301,38 @@ pub fn sdk_config(config: &CliConfig) -> Result<S2Config, CliError> {
 
     Ok(sdk_config)
 }
+
+/// Run a credential helper and use its trimmed stdout as the access token.
+///
+/// The helper runs once per invocation, when the client is built, so
+/// long-running commands such as `mirror`, `serve` and `lock` keep using the
+/// token they started with.
+fn access_token_from_command(command: &str) -> Result<String, CliConfigError> {
+    #[cfg(target_os = "windows")]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(target_os = "windows"))]
+    let output = Command::new("sh").args(["-c", command]).output();
+
+    let output = output.map_err(|e| CliConfigError::AccessTokenCommand(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        return Err(CliConfigError::AccessTokenCommand(if stderr.is_empty() {
+            output.status.to_string()
+        } else {
+            format!("{}: {}", output.status, stderr)
+        }));
+    }
+
+    let token = String::from_utf8(output.stdout)
+        .map_err(|_| CliConfigError::AccessTokenCommand("output is not valid UTF-8".to_owned()))?
+        .trim()
+        .to_owned();
+    if token.is_empty() {
+        return Err(CliConfigError::AccessTokenCommand(
+            "output is empty".to_owned(),
+        ));
+    }
+
+    Ok(token)
+}
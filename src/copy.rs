@@ -0,0 +1,197 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use colored::Colorize;
+use futures::{StreamExt, TryStreamExt, future};
+use s2_sdk::{
+    S2,
+    types::{AppendRecord, ReadFrom, ReadInput, ReadLimits, ReadStart, ReadStop, SequencedRecord},
+};
+use serde::Serialize;
+use tokio::select;
+
+use crate::{
+    checkpoint::ResumeState,
+    cli::{CpArgs, FenceArgs},
+    error::CliError,
+    ops,
+};
+
+/// Outcome of a copy between streams.
+#[derive(Debug, Serialize)]
+pub struct CopySummary {
+    /// Records appended to the destination by this run.
+    pub records: u64,
+    /// First source sequence number covered by this run.
+    pub src_start: u64,
+    /// Source sequence number this run stopped before, which follows the last
+    /// record copied if it was interrupted.
+    pub src_end: u64,
+}
+
+pub fn to_append_record(
+    record: SequencedRecord,
+    preserve_timestamps: bool,
+) -> Result<AppendRecord, CliError> {
+    let mut append_record = AppendRecord::new(record.body)
+        .and_then(|r| r.with_headers(record.headers))
+        .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+    if preserve_timestamps {
+        append_record = append_record.with_timestamp(record.timestamp);
+    }
+    Ok(append_record)
+}
+
+pub async fn run(s2: &S2, args: CpArgs) -> Result<CopySummary, CliError> {
+    let src_tail = ops::check_tail(s2, args.src.clone()).await?.seq_num;
+    let resuming = args.resume_state.as_ref().is_some_and(|path| path.exists());
+
+    let mut fencing_token = args.fencing_token.clone();
+    if let Some(token) = args.fence.clone() {
+        if !resuming {
+            ops::fence(
+                s2,
+                FenceArgs {
+                    uri: args.dst.clone(),
+                    new_fencing_token: token.clone(),
+                    fencing_token: args.fencing_token.clone(),
+                    match_seq_num: None,
+                },
+            )
+            .await?;
+            eprintln!(
+                "{}",
+                format!("✓ [FENCED] {} with token \"{}\"", args.dst, token)
+                    .green()
+                    .bold()
+            );
+        }
+        fencing_token = Some(token);
+    }
+
+    let mut resume = match &args.resume_state {
+        Some(path) => {
+            let dst_tail = ops::check_tail(s2, args.dst.clone()).await?.seq_num;
+            Some(ResumeState::load(
+                path,
+                &args.dst,
+                &args.src.to_string(),
+                dst_tail,
+            )?)
+        }
+        None => None,
+    };
+
+    let start = resume
+        .as_ref()
+        .map_or(0, ResumeState::offset)
+        .max(args.seq_num.unwrap_or(0));
+    let end = args.end_seq_num.map_or(src_tail, |end| end.min(src_tail));
+    if start >= end {
+        return Ok(CopySummary {
+            records: 0,
+            src_start: start,
+            src_end: start,
+        });
+    }
+    if resuming {
+        eprintln!(
+            "{}",
+            format!("↻ [RESUMING] from {} seq_num {start}", args.src)
+                .yellow()
+                .bold()
+        );
+    }
+
+    let batches = ops::read_session(
+        s2,
+        args.src.clone(),
+        ReadInput::new()
+            .with_start(ReadStart::new().with_from(ReadFrom::SeqNum(start)))
+            .with_stop(
+                ReadStop::new().with_limits(ReadLimits::new().with_count((end - start) as usize)),
+            ),
+    )
+    .await?;
+
+    let skip = resume.as_ref().map_or(0, ResumeState::skip);
+    let pending = resume.as_ref().map(ResumeState::pending);
+    let preserve_timestamps = args.preserve_timestamps;
+    // Source sequence numbers of the records sent, in the order they are acknowledged.
+    let sent = Arc::new(Mutex::new(VecDeque::new()));
+    let records = ops::records(batches)
+        .try_filter(|record| future::ready(!record.is_command_record()))
+        .skip(skip as usize)
+        .map({
+            let sent = sent.clone();
+            move |record| {
+                let record = record?;
+                if let Some(pending) = &pending {
+                    pending.push(record.seq_num + 1);
+                }
+                sent.lock().expect("not poisoned").push_back(record.seq_num);
+                to_append_record(record, preserve_timestamps)
+            }
+        });
+
+    let acks = ops::append(
+        s2,
+        Box::pin(records),
+        args.dst.clone(),
+        fencing_token,
+        resume.as_ref().map(ResumeState::match_seq_num),
+        *args.linger,
+    );
+    let mut acks = std::pin::pin!(acks);
+    let mut copied = 0;
+    let mut src_end = start;
+    let mut last_printed_batch_end: Option<u64> = None;
+
+    loop {
+        select! {
+            ack = acks.next() => {
+                match ack {
+                    Some(Ok(ack)) => {
+                        copied += 1;
+                        if let Some(seq_num) = sent.lock().expect("not poisoned").pop_front() {
+                            src_end = seq_num + 1;
+                        }
+                        if let Some(state) = &mut resume {
+                            state.acknowledged(ack.batch.end.seq_num)?;
+                        }
+                        if last_printed_batch_end.is_none_or(|end| end != ack.batch.end.seq_num) {
+                            last_printed_batch_end = Some(ack.batch.end.seq_num);
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "✓ [COPIED] {}..{} to {}",
+                                    ack.batch.start.seq_num, ack.batch.end.seq_num, args.dst
+                                )
+                                .green()
+                                .bold()
+                            );
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => {
+                        // Trailing command records were skipped rather than copied.
+                        src_end = end;
+                        break;
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                break;
+            }
+        }
+    }
+
+    Ok(CopySummary {
+        records: copied,
+        src_start: start,
+        src_end,
+    })
+}
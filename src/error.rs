@@ -45,9 +45,39 @@ pub enum CliError {
     #[error("Failed to initialize a `Record Reader`! {0}")]
     RecordReaderInit(String),
 
+    #[error("Failed to parse record: {0}")]
+    RecordParse(#[from] RecordParseError),
+
     #[error("Failed to write records: {0}")]
     RecordWrite(String),
 
+    #[error("Checkpoint {}: {}", .0.display(), .1)]
+    Checkpoint(std::path::PathBuf, String),
+
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Manifest {}: {}", .0.display(), .1)]
+    Manifest(std::path::PathBuf, String),
+
+    #[error("Basin {0} did not become active")]
+    BasinNotActive(String),
+
+    #[error("Operation failed on {0} of {1} streams")]
+    BulkOperation(usize, usize),
+
+    #[error("Lock {0}: {1}")]
+    Lock(String, String),
+
+    #[error("Handler failed on {0}: {1}")]
+    Exec(String, String),
+
+    #[error("Webhook failed on {0}: {1}")]
+    Webhook(String, String),
+
+    #[error("Server error: {0}")]
+    Serve(String),
+
     #[error("Benchmark verification failed: {0}")]
     #[diagnostic(help(
         "Ensure no other writers are mutating the stream during bench and retry the test."
@@ -179,6 +209,12 @@ pub enum CliConfigError {
     ))]
     Load(#[from] config::ConfigError),
 
+    #[error("Failed to read config file")]
+    Read(#[source] std::io::Error),
+
+    #[error("Failed to parse config file")]
+    Parse(#[source] toml::de::Error),
+
     #[error("Failed to write config file")]
     Write(#[source] std::io::Error),
 
@@ -190,7 +226,25 @@ pub enum CliConfigError {
 
     #[error("Missing access token")]
     #[diagnostic(help(
-        "Run `s2 config set access_token <token>` or set the `S2_ACCESS_TOKEN` environment variable."
+        "Run `s2 config set access_token <token>`, configure a credential helper with `s2 config set access_token_command <command>`, or set the `S2_ACCESS_TOKEN` environment variable."
     ))]
     MissingAccessToken,
+
+    #[error("Access token command failed: {0}")]
+    #[diagnostic(help(
+        "Check that `access_token_command` prints the access token to stdout and exits successfully."
+    ))]
+    AccessTokenCommand(String),
+
+    #[error("Profile '{0}' not found")]
+    #[diagnostic(help(
+        "Run `s2 config list-profiles` to see available profiles, or `s2 config create-profile {0}` to create it."
+    ))]
+    ProfileNotFound(String),
+
+    #[error("Profile '{0}' already exists")]
+    ProfileExists(String),
+
+    #[error("The default profile cannot be deleted")]
+    DefaultProfileDelete,
 }
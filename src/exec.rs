@@ -0,0 +1,585 @@
+//! Handling records read with a command, for `read --exec`.
+//!
+//! The command receives records on its stdin, encoded as `read` would output
+//! them. Records count as handled once the command exits zero or, for a worker,
+//! once it writes a line to stdout for each of them. Failures are retried with
+//! backoff, and then the records are either dead-lettered or reading stops.
+
+use std::{collections::VecDeque, process::Stdio, slice, time::Duration};
+
+use clap::ValueEnum;
+use colored::Colorize;
+use futures::StreamExt;
+use s2_sdk::{S2, types::SequencedRecord};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    select,
+};
+
+use crate::{
+    checkpoint::Checkpoint,
+    cli::{ExecArgs, ReadArgs},
+    error::{CliError, OpKind},
+    ops,
+    record_format::{JsonBase64Formatter, RecordWriter},
+    sink::RecordSink,
+    types::{S2BasinAndStreamUri, backoff},
+};
+
+/// How long to wait for a worker that closed its stdout to exit.
+const WORKER_EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExecMode {
+    /// Run the command for each record, with its metadata in the `S2_SEQ_NUM`,
+    /// `S2_TIMESTAMP` and `S2_HEADER_<NAME>` environment variables.
+    #[default]
+    Record,
+    /// Run the command for each batch read, with its range in the `S2_SEQ_NUM`,
+    /// `S2_LAST_SEQ_NUM` and `S2_RECORD_COUNT` environment variables.
+    Batch,
+    /// Run the command once and keep feeding it records. It must write a line to
+    /// stdout for each record handled, and is restarted if it exits early.
+    Worker,
+}
+
+/// Records not filtered out, and their encoding.
+struct Input<'a> {
+    records: Vec<&'a SequencedRecord>,
+    bytes: Vec<u8>,
+}
+
+impl Input<'_> {
+    fn describe(&self) -> String {
+        match self.records.as_slice() {
+            [record] => format!("seq_num {}", record.seq_num),
+            [first, .., last] => format!("seq_nums {}..={}", first.seq_num, last.seq_num),
+            [] => "no records".to_owned(),
+        }
+    }
+}
+
+/// A long-running command in [`ExecMode::Worker`].
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    acks: Lines<BufReader<ChildStdout>>,
+}
+
+impl Worker {
+    /// Feed inputs to the worker, returning how many it acknowledged in order,
+    /// and why it stopped short of all of them.
+    async fn feed(&mut self, inputs: &VecDeque<Input<'_>>) -> (usize, Result<(), String>) {
+        let Self { child, stdin, acks } = self;
+        let write = async {
+            for input in inputs {
+                stdin.write_all(&input.bytes).await?;
+            }
+            stdin.flush().await
+        };
+        let read = async {
+            let mut acked = 0;
+            while acked < inputs.len() {
+                match acks.next_line().await {
+                    Ok(Some(_)) => acked += 1,
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            acked
+        };
+        let (written, acked) = tokio::join!(write, read);
+        if acked == inputs.len() {
+            return (acked, Ok(()));
+        }
+
+        let reason = match tokio::time::timeout(WORKER_EXIT_TIMEOUT, child.wait()).await {
+            Ok(Ok(status)) => format!("worker exited with {status}"),
+            _ => match written {
+                Err(e) => format!("failed to write to worker: {e}"),
+                Ok(()) => "worker closed its stdout".to_owned(),
+            },
+        };
+        (acked, Err(reason))
+    }
+}
+
+pub struct Exec {
+    args: ExecArgs,
+    stream: S2BasinAndStreamUri,
+    sink: RecordSink<Vec<u8>>,
+    /// Output preceding the records given to each command, such as a CSV header.
+    preamble: Vec<u8>,
+    dead_letter: Option<File>,
+    worker: Option<Worker>,
+    handled: usize,
+    dead_lettered: usize,
+}
+
+impl Exec {
+    pub async fn new(args: &ReadArgs, stream: S2BasinAndStreamUri) -> Result<Self, CliError> {
+        let dead_letter = match &args.exec.dead_letter {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|e| CliError::RecordWrite(format!("{}: {e}", path.display())))?,
+            ),
+            None => None,
+        };
+        let mut sink = RecordSink::buffered(args).await?;
+        let preamble = sink.take();
+        Ok(Self {
+            args: args.exec.clone(),
+            stream,
+            sink,
+            preamble,
+            dead_letter,
+            worker: None,
+            handled: 0,
+            dead_lettered: 0,
+        })
+    }
+
+    /// Handle a batch of records, returning once each was handled or dead-lettered.
+    pub async fn handle(&mut self, records: &[SequencedRecord]) -> Result<(), CliError> {
+        match self.args.exec_mode {
+            ExecMode::Record => {
+                for record in records {
+                    if let Some(input) = self.encode(slice::from_ref(record)).await? {
+                        self.run(input, record_env(record)).await?;
+                    }
+                }
+            }
+            ExecMode::Batch => {
+                if let Some(input) = self.encode(records).await? {
+                    let env = batch_env(&input.records);
+                    self.run(input, env).await?;
+                }
+            }
+            ExecMode::Worker => {
+                let mut inputs = VecDeque::new();
+                for record in records {
+                    inputs.extend(self.encode(slice::from_ref(record)).await?);
+                }
+                self.feed(inputs).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for a worker to exit after its input ends.
+    pub async fn finish(&mut self) -> Result<(), CliError> {
+        let Some(Worker { mut child, .. }) = self.worker.take() else {
+            return Ok(());
+        };
+        let status = child.wait().await.map_err(|e| self.error(e))?;
+        if !status.success() {
+            return Err(self.error(format!("worker exited with {status}")));
+        }
+        Ok(())
+    }
+
+    pub fn report(&self) {
+        eprintln!(
+            "{}",
+            format!("✓ [HANDLED] {} records", self.handled)
+                .green()
+                .bold()
+        );
+        if self.dead_lettered > 0 {
+            eprintln!(
+                "{}",
+                format!("■ [DEAD-LETTERED] {} records", self.dead_lettered)
+                    .red()
+                    .bold()
+            );
+        }
+        self.sink.report();
+    }
+
+    fn error(&self, msg: impl ToString) -> CliError {
+        CliError::Exec(self.stream.to_string(), msg.to_string())
+    }
+
+    /// Encode the records that are not filtered out, if any.
+    async fn encode<'a>(
+        &mut self,
+        records: &'a [SequencedRecord],
+    ) -> Result<Option<Input<'a>>, CliError> {
+        let mut selected = Vec::new();
+        for record in records {
+            if self.sink.write(record, None).await? {
+                selected.push(record);
+            }
+        }
+        let bytes = self.sink.take();
+        Ok((!selected.is_empty()).then_some(Input {
+            records: selected,
+            bytes,
+        }))
+    }
+
+    fn command(&self) -> Command {
+        let (program, args) = self
+            .args
+            .command
+            .split_first()
+            .expect("clap requires a command");
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .env("S2_STREAM", self.stream.to_string())
+            .kill_on_drop(true);
+        command
+    }
+
+    fn spawn_error(&self, e: std::io::Error) -> CliError {
+        self.error(format!("failed to run {}: {e}", self.args.command[0]))
+    }
+
+    /// Run the command once on `input`, until it exits zero or attempts run out.
+    async fn run(&mut self, input: Input<'_>, env: Vec<(String, String)>) -> Result<(), CliError> {
+        let mut bytes = self.preamble.clone();
+        bytes.extend_from_slice(&input.bytes);
+        let mut attempt = 1;
+        loop {
+            let mut child = self
+                .command()
+                .envs(env.iter().map(|(k, v)| (k, v)))
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| self.spawn_error(e))?;
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            // The command may exit without reading all of its input.
+            _ = stdin.write_all(&bytes).await;
+            drop(stdin);
+            let status = child.wait().await.map_err(|e| self.spawn_error(e))?;
+            if status.success() {
+                self.handled += input.records.len();
+                return Ok(());
+            }
+            let reason = format!("command exited with {status}");
+            if !self.retry(attempt, &input, reason).await? {
+                return Ok(());
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn spawn_worker(&self) -> Result<Worker, CliError> {
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let acks = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+        // A worker that fails to read this fails on its first records.
+        _ = stdin.write_all(&self.preamble).await;
+        Ok(Worker { child, stdin, acks })
+    }
+
+    /// Feed inputs to the worker, restarting it when it fails.
+    async fn feed(&mut self, mut inputs: VecDeque<Input<'_>>) -> Result<(), CliError> {
+        let mut attempt = 1;
+        while !inputs.is_empty() {
+            let mut worker = match self.worker.take() {
+                Some(worker) => worker,
+                None => self.spawn_worker().await?,
+            };
+            let (acked, result) = worker.feed(&inputs).await;
+            self.handled += inputs
+                .drain(..acked)
+                .map(|input| input.records.len())
+                .sum::<usize>();
+            let Err(reason) = result else {
+                self.worker = Some(worker);
+                continue;
+            };
+
+            _ = worker.child.kill().await;
+            if acked > 0 {
+                attempt = 1;
+            }
+            let input = inputs.front().expect("unacknowledged input");
+            if self.retry(attempt, input, reason).await? {
+                attempt += 1;
+            } else {
+                inputs.pop_front();
+                attempt = 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// After a failed attempt, wait and return `true` to try again, or give up on the input.
+    async fn retry(
+        &mut self,
+        attempt: u32,
+        input: &Input<'_>,
+        reason: String,
+    ) -> Result<bool, CliError> {
+        if attempt < self.args.exec_attempts.get() {
+            let delay = backoff(*self.args.exec_backoff, attempt);
+            eprintln!(
+                "{}",
+                format!(
+                    "↻ [RETRYING] {} in {}: {reason}",
+                    input.describe(),
+                    humantime::format_duration(delay)
+                )
+                .yellow()
+                .bold()
+            );
+            tokio::time::sleep(delay).await;
+            return Ok(true);
+        }
+
+        let Some(file) = &mut self.dead_letter else {
+            return Err(CliError::Exec(input.describe(), reason));
+        };
+        for record in &input.records {
+            let mut line = Vec::new();
+            JsonBase64Formatter {}
+                .write_record(record, &mut line)
+                .await
+                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+            line.push(b'\n');
+            file.write_all(&line)
+                .await
+                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+        }
+        file.flush()
+            .await
+            .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+        self.dead_lettered += input.records.len();
+        eprintln!(
+            "{}",
+            format!("■ [DEAD-LETTERED] {}: {reason}", input.describe())
+                .red()
+                .bold()
+        );
+        Ok(false)
+    }
+}
+
+/// Environment variable for a header, e.g. `S2_HEADER_CONTENT_TYPE` for `content-type`.
+fn header_var(name: &[u8]) -> String {
+    let name: String = String::from_utf8_lossy(name)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("S2_HEADER_{name}")
+}
+
+fn record_env(record: &SequencedRecord) -> Vec<(String, String)> {
+    let mut env = vec![
+        ("S2_SEQ_NUM".to_owned(), record.seq_num.to_string()),
+        ("S2_TIMESTAMP".to_owned(), record.timestamp.to_string()),
+    ];
+    for header in &record.headers {
+        let value = String::from_utf8_lossy(&header.value);
+        // Command records have an empty header name, and env vars cannot hold NUL.
+        if header.name.is_empty() || value.contains('\0') {
+            continue;
+        }
+        env.push((header_var(&header.name), value.into_owned()));
+    }
+    env
+}
+
+fn batch_env(records: &[&SequencedRecord]) -> Vec<(String, String)> {
+    let (first, last) = (records[0], records[records.len() - 1]);
+    vec![
+        ("S2_SEQ_NUM".to_owned(), first.seq_num.to_string()),
+        ("S2_LAST_SEQ_NUM".to_owned(), last.seq_num.to_string()),
+        ("S2_RECORD_COUNT".to_owned(), records.len().to_string()),
+    ]
+}
+
+/// Read `uri` and handle its records with the command, checkpointing after each batch.
+pub async fn run(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    args: &ReadArgs,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<(), CliError> {
+    let mut exec = Exec::new(args, uri.clone()).await?;
+    let mut batches = ops::read(s2, uri, args).await?;
+    loop {
+        select! {
+            batch = batches.next() => match batch {
+                Some(Ok(batch)) => {
+                    let Some(last) = batch.records.last() else {
+                        continue;
+                    };
+                    exec.handle(&batch.records).await?;
+                    if let Some(checkpoint) = checkpoint {
+                        checkpoint.save(last.seq_num)?;
+                    }
+                }
+                Some(Err(e)) => return Err(CliError::op(OpKind::Read, e)),
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                break;
+            }
+        }
+    }
+    exec.finish().await?;
+    exec.report();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::Parser;
+    use s2_api::v1::stream::proto;
+    use s2_sdk::types::SequencedRecord;
+
+    use super::{Exec, header_var};
+    use crate::cli::{Cli, Command};
+
+    fn records(bodies: &[&str]) -> Vec<SequencedRecord> {
+        bodies
+            .iter()
+            .zip(0..)
+            .map(|(body, seq_num)| {
+                proto::SequencedRecord {
+                    seq_num,
+                    timestamp: 1000 + seq_num,
+                    headers: Vec::new(),
+                    body: body.to_string().into(),
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    /// Handle records with `sh -c script`, run in `dir`.
+    async fn exec_with(dir: &Path, args: &[&str], script: &str) -> Exec {
+        let script = format!("cd {} && {script}", dir.display());
+        let argv = ["s2", "read", "s2://my-basin/events", "--exec"]
+            .into_iter()
+            .chain(["--exec-backoff", "1ms"])
+            .chain(args.iter().copied())
+            .chain(["--", "sh", "-c", &script]);
+        let Command::Read(args) = Cli::try_parse_from(argv).unwrap().command else {
+            unreachable!("parsed a read command");
+        };
+        Exec::new(&args, "s2://my-basin/events".parse().unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn lines(path: impl AsRef<Path>) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_exec_retries_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Fails twice on each record, then handles it.
+        let mut exec = exec_with(
+            dir.path(),
+            &[],
+            "echo $S2_SEQ_NUM >> attempts; cat >> handled; \
+             [ $(grep -c \"^$S2_SEQ_NUM$\" attempts) -ge 3 ]",
+        )
+        .await;
+        exec.handle(&records(&["a", "b"])).await.unwrap();
+        exec.finish().await.unwrap();
+
+        assert_eq!(exec.handled, 2);
+        assert_eq!(exec.dead_lettered, 0);
+        assert_eq!(
+            lines(dir.path().join("attempts")),
+            ["0", "0", "0", "1", "1", "1"]
+        );
+        assert_eq!(
+            lines(dir.path().join("handled")),
+            ["a", "a", "a", "b", "b", "b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exec_dead_letters_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dead_letter = dir.path().join("dead-letter");
+        let mut exec = exec_with(
+            dir.path(),
+            &[
+                "--exec-mode",
+                "batch",
+                "--exec-attempts",
+                "2",
+                "--dead-letter",
+                dead_letter.to_str().unwrap(),
+            ],
+            "echo $S2_RECORD_COUNT >> attempts; exit 3",
+        )
+        .await;
+        exec.handle(&records(&["a", "b"])).await.unwrap();
+
+        assert_eq!(exec.handled, 0);
+        assert_eq!(exec.dead_lettered, 2);
+        assert_eq!(lines(dir.path().join("attempts")), ["2", "2"]);
+        let dead_lettered = lines(&dead_letter);
+        assert_eq!(dead_lettered.len(), 2);
+        assert!(dead_lettered[0].contains(r#""seq_num":0"#));
+        assert!(dead_lettered[1].contains(r#""seq_num":1"#));
+
+        // Without a dead-letter file, reading stops.
+        let mut exec = exec_with(dir.path(), &["--exec-attempts", "2"], "exit 3").await;
+        assert!(exec.handle(&records(&["a"])).await.is_err());
+        assert_eq!(exec.handled, 0);
+    }
+
+    #[tokio::test]
+    async fn test_exec_worker_restarts_after_partial_acks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // The first worker acknowledges one record and exits, later ones all of them.
+        let mut exec = exec_with(
+            dir.path(),
+            &["--exec-mode", "worker"],
+            "echo started >> starts; \
+             if [ $(wc -l < starts) -eq 1 ]; then read -r line; echo ok; exit 1; fi; \
+             while read -r line; do echo \"$line\" >> handled; echo ok; done",
+        )
+        .await;
+        exec.handle(&records(&["a", "b", "c"])).await.unwrap();
+        assert_eq!(exec.handled, 3);
+        // The running worker handles later batches.
+        exec.handle(&records(&["d"])).await.unwrap();
+        exec.finish().await.unwrap();
+
+        assert_eq!(exec.handled, 4);
+        assert_eq!(exec.dead_lettered, 0);
+        assert_eq!(lines(dir.path().join("starts")).len(), 2);
+        assert_eq!(lines(dir.path().join("handled")), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_header_var() {
+        assert_eq!(header_var(b"content-type"), "S2_HEADER_CONTENT_TYPE");
+        assert_eq!(header_var(b"user.Id9"), "S2_HEADER_USER_ID9");
+    }
+}
@@ -0,0 +1,139 @@
+//! Client-side filtering of records by headers and body.
+
+use std::str::FromStr;
+
+use colored::Colorize;
+use regex::bytes::Regex;
+use s2_sdk::types::SequencedRecord;
+
+use crate::{cli::RecordFilterArgs, types::Glob};
+
+#[derive(Debug, Clone)]
+pub enum ValueMatcher {
+    Glob(Glob),
+    Regex(Regex),
+}
+
+/// Filter on a header value, of the form `name=glob` or `name~=regex`.
+#[derive(Debug, Clone)]
+pub struct HeaderFilter {
+    name: String,
+    value: ValueMatcher,
+}
+
+impl HeaderFilter {
+    fn matches(&self, record: &SequencedRecord) -> bool {
+        record
+            .headers
+            .iter()
+            .filter(|h| h.name.as_ref() == self.name.as_bytes())
+            .any(|h| match &self.value {
+                ValueMatcher::Glob(glob) => glob.matches(&String::from_utf8_lossy(&h.value)),
+                ValueMatcher::Regex(regex) => regex.is_match(&h.value),
+            })
+    }
+}
+
+impl FromStr for HeaderFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `name=glob` or `name~=regex`, got `{s}`"))?;
+        let (name, value) = match name.strip_suffix('~') {
+            Some(name) => (
+                name,
+                ValueMatcher::Regex(Regex::new(value).map_err(|e| e.to_string())?),
+            ),
+            None => (name, ValueMatcher::Glob(value.parse()?)),
+        };
+        if name.is_empty() {
+            return Err("header name cannot be empty".to_owned());
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            value,
+        })
+    }
+}
+
+/// Matches records against all header filters and the body pattern,
+/// counting records scanned and matched.
+#[derive(Debug)]
+pub struct RecordFilter {
+    headers: Vec<HeaderFilter>,
+    body: Option<Regex>,
+    pub scanned: u64,
+    pub matched: u64,
+}
+
+impl RecordFilter {
+    /// Returns `None` if no filters are specified.
+    pub fn new(args: &RecordFilterArgs) -> Option<Self> {
+        if args.header_filters.is_empty() && args.body_grep.is_none() {
+            return None;
+        }
+        Some(Self {
+            headers: args.header_filters.clone(),
+            body: args.body_grep.clone(),
+            scanned: 0,
+            matched: 0,
+        })
+    }
+
+    pub fn matches(&mut self, record: &SequencedRecord) -> bool {
+        self.scanned += 1;
+        let matched = self.headers.iter().all(|filter| filter.matches(record))
+            && self
+                .body
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&record.body));
+        if matched {
+            self.matched += 1;
+        }
+        matched
+    }
+
+    /// Report how many records matched.
+    pub fn report(&self) {
+        eprintln!(
+            "{}",
+            format!("⦿ {} of {} records matched", self.matched, self.scanned)
+                .blue()
+                .bold()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderFilter, ValueMatcher};
+
+    #[test]
+    fn test_header_filter_parse() {
+        let filter: HeaderFilter = "level=warn*".parse().unwrap();
+        assert_eq!(filter.name, "level");
+        let ValueMatcher::Glob(glob) = &filter.value else {
+            panic!("expected glob");
+        };
+        assert!(glob.matches("warning"));
+        assert!(!glob.matches("error"));
+
+        let filter: HeaderFilter = "tenant~=^acme-\\d+$".parse().unwrap();
+        assert_eq!(filter.name, "tenant");
+        let ValueMatcher::Regex(regex) = &filter.value else {
+            panic!("expected regex");
+        };
+        assert!(regex.is_match(b"acme-42"));
+        assert!(!regex.is_match(b"acme-x"));
+
+        let filter: HeaderFilter = "url=a=b".parse().unwrap();
+        assert_eq!(filter.name, "url");
+
+        assert!("level".parse::<HeaderFilter>().is_err());
+        assert!("=warn".parse::<HeaderFilter>().is_err());
+        assert!("level~=(".parse::<HeaderFilter>().is_err());
+        assert!("level=[a".parse::<HeaderFilter>().is_err());
+    }
+}
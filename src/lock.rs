@@ -0,0 +1,454 @@
+//! Leases over a stream, for mutual exclusion between processes.
+//!
+//! A lock is held by fencing its stream with a fresh token and then appending
+//! a lease record with that token. Lease records are JSON bodies tagged with the
+//! `s2-lock-lease` header. A holder renews its lease by appending another record
+//! before it expires, and releases it with a final record. Because every lease
+//! append is conditioned on the holder's fencing token, a holder whose expired
+//! lease was stolen finds out on its next renewal. Records before the latest
+//! lease are trimmed after each renewal and release, so the stream stays small.
+//!
+//! Expiry is judged by wall clock time, so clocks of participating hosts are
+//! assumed to be roughly in sync relative to the lease duration.
+
+use std::{
+    process::ExitStatus,
+    time::{Duration, SystemTime},
+};
+
+use colored::Colorize;
+use futures::TryStreamExt;
+use s2_sdk::{
+    S2,
+    types::{
+        AppendRecord, CommandRecord, FencingToken, Header, ReadFrom, ReadInput, ReadLimits,
+        ReadStart, ReadStop, S2Error, SequencedRecord,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{FenceArgs, LockAcquireArgs, LockReleaseArgs, LockRenewArgs, LockRunArgs},
+    error::CliError,
+    ops, record_format,
+    types::S2BasinAndStreamUri,
+};
+
+const LEASE_HEADER: &str = "s2-lock-lease";
+
+/// Number of records read at a time when scanning backwards for the latest lease.
+const LEASE_SCAN_WINDOW: u64 = 64;
+
+/// How often to check whether a held lock has been released, when waiting.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaseEvent {
+    Acquire,
+    Renew,
+    Release,
+}
+
+/// Lease record, as appended to the lock stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub event: LeaseEvent,
+    pub holder: String,
+    pub token: String,
+    /// Milliseconds since Unix epoch after which the lease can be taken over.
+    pub expires_at: u64,
+}
+
+impl Lease {
+    fn is_held(&self, now: u64) -> bool {
+        self.event != LeaseEvent::Release && self.expires_at > now
+    }
+
+    fn from_record(record: &SequencedRecord) -> Option<Self> {
+        record
+            .headers
+            .iter()
+            .any(|h| h.name.as_ref() == LEASE_HEADER.as_bytes())
+            .then(|| serde_json::from_slice(&record.body).ok())
+            .flatten()
+    }
+
+    fn to_record(&self) -> Result<AppendRecord, CliError> {
+        AppendRecord::new(serde_json::to_vec(self)?)
+            .and_then(|r| r.with_headers([Header::new(LEASE_HEADER, "")]))
+            .map_err(|e| CliError::RecordWrite(e.to_string()))
+    }
+}
+
+/// Parse a lease TTL, which must be at least a second so that it can be renewed
+/// every third of it.
+pub fn parse_lease_ttl(s: &str) -> Result<humantime::Duration, String> {
+    let ttl: humantime::Duration = s.parse().map_err(|e| format!("{e}"))?;
+    if *ttl < Duration::from_secs(1) {
+        return Err("must be at least 1s".to_owned());
+    }
+    Ok(ttl)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn lock_error(uri: &S2BasinAndStreamUri, msg: impl ToString) -> CliError {
+    CliError::Lock(uri.to_string(), msg.to_string())
+}
+
+fn is_condition_failed(e: &CliError) -> bool {
+    matches!(e, CliError::Operation(_, S2Error::AppendConditionFailed(_)))
+}
+
+/// The latest lease in `records`, or `Some(None)` if the stream was fenced after
+/// it, which invalidates its token. `None` if there is neither.
+fn latest_lease(records: &[SequencedRecord]) -> Option<Option<Lease>> {
+    records.iter().rev().find_map(|record| {
+        if let Some(lease) = Lease::from_record(record) {
+            return Some(Some(lease));
+        }
+        match record_format::CommandRecord::from_record(record) {
+            Some(record_format::CommandRecord::Fence { .. }) => Some(None),
+            _ => None,
+        }
+    })
+}
+
+/// The stream tail, and the latest lease record before it.
+///
+/// Scans backwards from the tail one window at a time, past other records such
+/// as trims and stray appends, until a lease or a fence is found.
+async fn current_lease(
+    s2: &S2,
+    uri: &S2BasinAndStreamUri,
+) -> Result<(u64, Option<Lease>), CliError> {
+    let tail = ops::check_tail(s2, uri.clone()).await?.seq_num;
+    let mut end = tail;
+    while end > 0 {
+        let start = end.saturating_sub(LEASE_SCAN_WINDOW);
+        let records = async {
+            let batches = ops::read_session(
+                s2,
+                uri.clone(),
+                ReadInput::new()
+                    .with_start(ReadStart::new().with_from(ReadFrom::SeqNum(start)))
+                    .with_stop(
+                        ReadStop::new()
+                            .with_limits(ReadLimits::new().with_count((end - start) as usize)),
+                    ),
+            )
+            .await?;
+            ops::records(batches)
+                .try_take_while(|record| futures::future::ready(Ok(record.seq_num < end)))
+                .try_collect::<Vec<_>>()
+                .await
+        };
+        let records = match records.await {
+            Ok(records) => records,
+            Err(CliError::Operation(_, S2Error::ReadUnwritten(_))) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if let Some(lease) = latest_lease(&records) {
+            return Ok((tail, lease));
+        }
+        // Reading a trimmed window starts at the first record kept, so nothing precedes it.
+        if records.first().is_none_or(|record| record.seq_num > start) {
+            break;
+        }
+        end = start;
+    }
+    Ok((tail, None))
+}
+
+/// Acquire the lock, waiting for the current holder if `args.wait` is set.
+pub async fn acquire(s2: &S2, args: &LockAcquireArgs) -> Result<Lease, CliError> {
+    loop {
+        let (tail, lease) = current_lease(s2, &args.uri).await?;
+        let now = now_millis();
+        if let Some(lease) = lease.filter(|lease| lease.is_held(now)) {
+            if !args.wait {
+                return Err(lock_error(
+                    &args.uri,
+                    format!(
+                        "held by \"{}\" for another {}ms",
+                        lease.holder,
+                        lease.expires_at - now
+                    ),
+                ));
+            }
+            let remaining = Duration::from_millis(lease.expires_at - now);
+            tokio::time::sleep(remaining.min(WAIT_POLL_INTERVAL)).await;
+            continue;
+        }
+
+        // Fencing at the observed tail fails if another process got there first.
+        let token = FencingToken::generate(16).expect("valid fencing token");
+        let fenced = ops::fence(
+            s2,
+            FenceArgs {
+                uri: args.uri.clone(),
+                new_fencing_token: token.clone(),
+                fencing_token: None,
+                match_seq_num: Some(tail),
+            },
+        )
+        .await;
+        match fenced {
+            Ok(_) => {}
+            Err(e) if is_condition_failed(&e) && args.wait => continue,
+            Err(e) if is_condition_failed(&e) => {
+                return Err(lock_error(
+                    &args.uri,
+                    "acquired concurrently by another holder",
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+
+        let lease = Lease {
+            event: LeaseEvent::Acquire,
+            holder: args.holder.clone(),
+            token: token.to_string(),
+            expires_at: now_millis() + args.ttl.as_millis() as u64,
+        };
+        match ops::append_record(
+            s2,
+            args.uri.clone(),
+            lease.to_record()?,
+            Some(token),
+            Some(tail + 1),
+        )
+        .await
+        {
+            Ok(_) => return Ok(lease),
+            Err(e) if is_condition_failed(&e) && args.wait => continue,
+            Err(e) if is_condition_failed(&e) => {
+                return Err(lock_error(
+                    &args.uri,
+                    "acquired concurrently by another holder",
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Append a lease record for the current holder of `token`.
+async fn append_lease(
+    s2: &S2,
+    uri: &S2BasinAndStreamUri,
+    token: &FencingToken,
+    event: LeaseEvent,
+    ttl: Duration,
+) -> Result<Lease, CliError> {
+    let (_, current) = current_lease(s2, uri).await?;
+    let holder = match current {
+        Some(lease) if lease.token == token.to_string() && lease.event != LeaseEvent::Release => {
+            lease.holder
+        }
+        _ => return Err(lock_error(uri, "not held with this token")),
+    };
+    let lease = Lease {
+        event,
+        holder,
+        token: token.to_string(),
+        expires_at: now_millis() + ttl.as_millis() as u64,
+    };
+    let ack = ops::append_record(
+        s2,
+        uri.clone(),
+        lease.to_record()?,
+        Some(token.clone()),
+        None,
+    )
+    .await
+    .map_err(|e| {
+        if is_condition_failed(&e) {
+            lock_error(uri, "lease was taken over by another holder")
+        } else {
+            e
+        }
+    })?;
+
+    // The lease is already recorded, so failing to trim older records is not fatal.
+    if let Err(e) = ops::append_record(
+        s2,
+        uri.clone(),
+        CommandRecord::trim(ack.start.seq_num).into(),
+        Some(token.clone()),
+        None,
+    )
+    .await
+    {
+        tracing::warn!(%uri, error = %e, "failed to trim lock stream");
+    }
+    Ok(lease)
+}
+
+pub async fn renew(s2: &S2, args: &LockRenewArgs) -> Result<Lease, CliError> {
+    append_lease(s2, &args.uri, &args.token, LeaseEvent::Renew, *args.ttl).await
+}
+
+pub async fn release(s2: &S2, args: &LockReleaseArgs) -> Result<Lease, CliError> {
+    append_lease(
+        s2,
+        &args.uri,
+        &args.token,
+        LeaseEvent::Release,
+        Duration::ZERO,
+    )
+    .await
+}
+
+/// Run a command while holding the lock, renewing the lease until the command exits.
+///
+/// The command is killed if the lease is lost.
+pub async fn run(s2: &S2, args: LockRunArgs) -> Result<ExitStatus, CliError> {
+    let lease = acquire(s2, &args.lock).await?;
+    let uri = &args.lock.uri;
+    let ttl = *args.lock.ttl;
+    let token: FencingToken = lease.token.parse().expect("generated token is valid");
+    eprintln!(
+        "{}",
+        format!("✓ [ACQUIRED] {uri} as \"{}\"", lease.holder)
+            .green()
+            .bold()
+    );
+
+    let (program, program_args) = args.command.split_first().expect("clap requires a command");
+    let mut child = tokio::process::Command::new(program)
+        .args(program_args)
+        .env("S2_LOCK_TOKEN", &lease.token)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| lock_error(uri, format!("failed to run {program}: {e}")))?;
+
+    let mut renewals = tokio::time::interval(ttl / 3);
+    renewals.tick().await;
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                let status = status.map_err(|e| lock_error(uri, e))?;
+                // Report a failed release without masking the command's exit status.
+                match release(s2, &LockReleaseArgs { uri: uri.clone(), token }).await {
+                    Ok(_) => eprintln!("{}", format!("✓ [RELEASED] {uri}").green().bold()),
+                    Err(e) => eprintln!("{}", format!("✗ [RELEASE FAILED] {uri}: {e}").red().bold()),
+                }
+                return Ok(status);
+            }
+            _ = renewals.tick() => {
+                if let Err(e) = renew(s2, &LockRenewArgs { uri: uri.clone(), token: token.clone(), ttl: args.lock.ttl }).await {
+                    _ = child.kill().await;
+                    eprintln!("{}", format!("■ [LOST] {uri}").red().bold());
+                    return Err(e);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                _ = child.kill().await;
+                release(s2, &LockReleaseArgs { uri: uri.clone(), token }).await?;
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                return Err(lock_error(uri, "interrupted"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use s2_api::v1::stream::proto;
+    use s2_sdk::types::SequencedRecord;
+
+    use super::{LEASE_HEADER, Lease, LeaseEvent, latest_lease, parse_lease_ttl};
+
+    fn record(seq_num: u64, header: (&str, &str), body: &str) -> SequencedRecord {
+        proto::SequencedRecord {
+            seq_num,
+            timestamp: seq_num,
+            headers: vec![proto::Header {
+                name: header.0.to_owned().into(),
+                value: header.1.to_owned().into(),
+            }],
+            body: body.to_owned().into(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_latest_lease() {
+        let lease = serde_json::to_string(&Lease {
+            event: LeaseEvent::Acquire,
+            holder: "worker-1".to_owned(),
+            token: "token".to_owned(),
+            expires_at: 1000,
+        })
+        .unwrap();
+        let mut records = vec![
+            record(0, ("", "fence"), "token"),
+            record(1, (LEASE_HEADER, ""), &lease),
+        ];
+        // Other records after the lease do not hide it.
+        records.extend((2..100).map(|seq_num| record(seq_num, ("other", ""), "stray")));
+        let found = latest_lease(&records).unwrap().unwrap();
+        assert_eq!(found.holder, "worker-1");
+
+        // A later fence invalidates the lease.
+        records.push(record(100, ("", "fence"), "other-token"));
+        assert!(latest_lease(&records).unwrap().is_none());
+
+        // Without either, the search goes on.
+        assert!(latest_lease(&records[2..100]).is_none());
+    }
+
+    #[test]
+    fn test_lease_is_held() {
+        let lease = Lease {
+            event: LeaseEvent::Acquire,
+            holder: "worker-1".to_owned(),
+            token: "token".to_owned(),
+            expires_at: 1000,
+        };
+        assert!(lease.is_held(999));
+        assert!(!lease.is_held(1000));
+
+        let released = Lease {
+            event: LeaseEvent::Release,
+            ..lease
+        };
+        assert!(!released.is_held(0));
+    }
+
+    #[test]
+    fn test_lease_json() {
+        let lease = Lease {
+            event: LeaseEvent::Renew,
+            holder: "worker-1".to_owned(),
+            token: "token".to_owned(),
+            expires_at: 1000,
+        };
+        let json = serde_json::to_string(&lease).unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"renew","holder":"worker-1","token":"token","expires_at":1000}"#
+        );
+        let parsed: Lease = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.event, LeaseEvent::Renew);
+        assert!(lease.to_record().is_ok());
+    }
+
+    #[test]
+    fn test_parse_lease_ttl() {
+        assert_eq!(*parse_lease_ttl("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(*parse_lease_ttl("1s").unwrap(), Duration::from_secs(1));
+        assert!(parse_lease_ttl("0s").is_err());
+        assert!(parse_lease_ttl("500ms").is_err());
+        assert!(parse_lease_ttl("soon").is_err());
+    }
+}
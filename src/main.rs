@@ -1,41 +1,67 @@
+mod archive;
 mod bench;
+mod bulk;
+mod checkpoint;
 mod cli;
 mod config;
+mod copy;
 mod error;
+mod exec;
+mod filter;
+mod lock;
+mod merge;
+mod mirror;
 mod ops;
+mod output;
+mod plan;
 mod record_format;
+mod serve;
+mod shard;
+mod sink;
+mod stats;
 mod types;
+mod webhook;
 
 use std::pin::Pin;
 use std::time::Duration;
 
+use bulk::StreamOutcome;
+use checkpoint::{Checkpoint, ResumeState};
 use clap::Parser;
-use cli::ConfigCommand;
-use cli::{Cli, Command, ListBasinsArgs, ListStreamsArgs};
+use cli::{AppendArgs, Cli, Command, ListBasinsArgs, ListStreamsArgs};
+use cli::{ConfigCommand, LockCommand};
 use colored::Colorize;
 use config::{
-    ConfigKey, load_cli_config, load_config_file, sdk_config, set_config_value, unset_config_value,
+    ConfigKey, create_profile, delete_profile, load_cli_config, load_config_file, sdk_config,
+    set_config_value, switch_profile, unset_config_value,
 };
-use error::{CliError, OpKind};
-use futures::{Stream, StreamExt, TryStreamExt};
+use error::{CliError, OpKind, RecordParseError};
+use futures::{Stream, StreamExt, TryStreamExt, future};
 use json_to_table::json_to_table;
+use output::OutputFormat;
 use record_format::{
-    JsonBase64Formatter, JsonFormatter, RecordFormat, RecordParser, RecordWriter, TextFormatter,
+    AppendRecords, BinaryFormatter, CsvFormatter, InputOffset, JsonBase64Formatter, JsonFormatter,
+    ParsedRecord, RecordFormat, RecordParser, RecordsIn, TextFormatter,
 };
 use s2_sdk::{
     S2,
     types::{
-        AppendRetryPolicy, BasinState, CreateStreamInput, DeleteOnEmptyConfig, DeleteStreamInput,
-        MeteredBytes, Metric, RetentionPolicy, RetryConfig, StreamConfig as SdkStreamConfig,
-        StreamName, TimestampingConfig, TimestampingMode,
+        AppendRetryPolicy, BasinName, BasinState, CreateStreamInput, DeleteOnEmptyConfig,
+        DeleteStreamInput, MeteredBytes, Metric, RetentionPolicy, RetryConfig,
+        StreamConfig as SdkStreamConfig, StreamName, TimestampingConfig, TimestampingMode,
     },
 };
+use shard::ShardKey;
+use sink::RecordSink;
 use strum::VariantNames;
 use tabled::{Table, Tabled};
-use tokio::io::AsyncWriteExt;
 use tokio::select;
 use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt};
-use types::{AccessTokenInfo, BasinConfig, S2BasinAndMaybeStreamUri, StreamConfig};
+use types::{
+    AccessTokenInfo, AppendAck, BasinConfig, BasinInfo, S2BasinAndMaybeStreamUri,
+    S2BasinAndStreamSelector, S2BasinAndStreamUri, ShardAppendAck, StreamConfig, StreamInfo,
+    StreamPosition,
+};
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
@@ -70,26 +96,44 @@ async fn run() -> Result<(), CliError> {
         .with(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    let output = commands.output_format;
+    if output != OutputFormat::Table {
+        colored::control::set_override(false);
+    }
+
     if let Command::Config(config_cmd) = &commands.command {
+        let profile = commands.profile.as_deref();
         match config_cmd {
             ConfigCommand::List => {
-                let config = load_config_file()?;
-                for k in ConfigKey::VARIANTS {
-                    if let Ok(key) = k.parse::<ConfigKey>()
-                        && let Some(v) = config.get(key)
-                    {
-                        println!("{} = {}", k, v);
+                let file = load_config_file()?;
+                let config = file.profile(file.resolve_profile(profile))?;
+                let values: serde_json::Map<String, serde_json::Value> = ConfigKey::VARIANTS
+                    .iter()
+                    .filter_map(|k| {
+                        let key = k.parse::<ConfigKey>().ok()?;
+                        Some((k.to_string(), config.get(key)?.into()))
+                    })
+                    .collect();
+                if output.is_structured() {
+                    output::print_value(output, &values)?;
+                } else {
+                    for (k, v) in values {
+                        println!("{} = {}", k, v.as_str().unwrap_or_default());
                     }
                 }
             }
             ConfigCommand::Get { key } => {
-                let config = load_config_file()?;
-                if let Some(v) = config.get(*key) {
+                let file = load_config_file()?;
+                let config = file.profile(file.resolve_profile(profile))?;
+                let value = config.get(*key);
+                if output.is_structured() {
+                    output::print_value(output, &value)?;
+                } else if let Some(v) = value {
                     println!("{}", v);
                 }
             }
             ConfigCommand::Set { key, value } => {
-                let saved_path = set_config_value(*key, value.clone())?;
+                let saved_path = set_config_value(profile, *key, value.clone())?;
                 eprintln!("{}", format!("✓ {} set", key).green().bold());
                 eprintln!(
                     "  Configuration saved to: {}",
@@ -97,18 +141,64 @@ async fn run() -> Result<(), CliError> {
                 );
             }
             ConfigCommand::Unset { key } => {
-                let saved_path = unset_config_value(*key)?;
+                let saved_path = unset_config_value(profile, *key)?;
                 eprintln!("{}", format!("✓ {} unset", key).green().bold());
                 eprintln!(
                     "  Configuration saved to: {}",
                     saved_path.display().to_string().cyan()
                 );
             }
+            ConfigCommand::ListProfiles => {
+                let file = load_config_file()?;
+                let active = file.resolve_profile(profile);
+                if output.is_structured() {
+                    let profiles: Vec<_> = file
+                        .profile_names()
+                        .map(|name| serde_json::json!({ "name": name, "active": name == active }))
+                        .collect();
+                    output::print_value(output, &profiles)?;
+                    return Ok(());
+                }
+                for name in file.profile_names() {
+                    if name == active {
+                        println!("{} {}", "*".green().bold(), name.green().bold());
+                    } else {
+                        println!("  {}", name);
+                    }
+                }
+            }
+            ConfigCommand::CreateProfile { name } => {
+                let saved_path = create_profile(name)?;
+                eprintln!("{}", format!("✓ Profile '{}' created", name).green().bold());
+                eprintln!(
+                    "  Configuration saved to: {}",
+                    saved_path.display().to_string().cyan()
+                );
+            }
+            ConfigCommand::SwitchProfile { name } => {
+                let saved_path = switch_profile(name)?;
+                eprintln!(
+                    "{}",
+                    format!("✓ Switched to profile '{}'", name).green().bold()
+                );
+                eprintln!(
+                    "  Configuration saved to: {}",
+                    saved_path.display().to_string().cyan()
+                );
+            }
+            ConfigCommand::DeleteProfile { name } => {
+                let saved_path = delete_profile(name)?;
+                eprintln!("{}", format!("✓ Profile '{}' deleted", name).green().bold());
+                eprintln!(
+                    "  Configuration saved to: {}",
+                    saved_path.display().to_string().cyan()
+                );
+            }
         }
         return Ok(());
     }
 
-    let cli_config = load_cli_config()?;
+    let cli_config = load_cli_config(commands.profile.as_deref())?;
     let sdk_config = sdk_config(&cli_config)?;
     let s2 = S2::new(sdk_config.clone()).map_err(CliError::SdkInit)?;
 
@@ -151,15 +241,11 @@ async fn run() -> Result<(), CliError> {
                     no_auto_paginate: args.no_auto_paginate,
                 };
 
-                let mut streams = ops::list_streams(&s2, list_streams_args).await?;
-                while let Some(stream_info) = streams.try_next().await? {
-                    println!(
-                        "s2://{}/{} {}",
-                        basin,
-                        stream_info.name,
-                        stream_info.created_at.to_string().green(),
-                    );
-                }
+                let streams = ops::list_streams(&s2, list_streams_args).await?;
+                output::print_list(output, streams.map_ok(StreamInfo::from), |info| {
+                    format!("s2://{}/{} {}", basin, info.name, info.created_at.green())
+                })
+                .await?;
             } else {
                 // List basins
                 let list_basins_args = ListBasinsArgs {
@@ -179,26 +265,20 @@ async fn run() -> Result<(), CliError> {
                     no_auto_paginate: args.no_auto_paginate,
                 };
 
-                let mut basins = ops::list_basins(&s2, list_basins_args).await?;
-                while let Some(basin_info) = basins.try_next().await? {
-                    println!(
-                        "{} {}",
-                        basin_info.name,
-                        format_basin_state(basin_info.state)
-                    );
-                }
+                let basins = ops::list_basins(&s2, list_basins_args).await?;
+                output::print_list(output, basins.map_ok(BasinInfo::from), |info| {
+                    format!("{} {}", info.name, format_basin_state(&info.state))
+                })
+                .await?;
             }
         }
 
         Command::ListBasins(args) => {
-            let mut basins = ops::list_basins(&s2, args).await?;
-            while let Some(basin_info) = basins.try_next().await? {
-                println!(
-                    "{} {}",
-                    basin_info.name,
-                    format_basin_state(basin_info.state)
-                );
-            }
+            let basins = ops::list_basins(&s2, args).await?;
+            output::print_list(output, basins.map_ok(BasinInfo::from), |info| {
+                format!("{} {}", info.name, format_basin_state(&info.state))
+            })
+            .await?;
         }
 
         Command::CreateBasin(args) => {
@@ -210,36 +290,54 @@ async fn run() -> Result<(), CliError> {
                 BasinState::Deleting => "Basin is being deleted".red().bold(),
             };
             eprintln!("{message}");
+            if output.is_structured() {
+                output::print_value(output, &BasinInfo::from(info))?;
+            }
         }
 
         Command::DeleteBasin { basin } => {
-            ops::delete_basin(&s2, &basin.into()).await?;
+            let basin: BasinName = basin.into();
+            ops::delete_basin(&s2, &basin).await?;
             eprintln!("{}", "✓ Basin deletion requested".green().bold());
+            if output.is_structured() {
+                output::print_value(output, &serde_json::json!({ "basin": basin.to_string() }))?;
+            }
         }
 
         Command::GetBasinConfig { basin } => {
             let basin_config: BasinConfig = ops::get_basin_config(&s2, &basin.into()).await?.into();
-            println!("{}", json_to_table(&serde_json::to_value(&basin_config)?));
+            output::print_value(output, &basin_config)?;
         }
 
         Command::ReconfigureBasin(args) => {
             let config = ops::reconfigure_basin(&s2, args).await?;
 
             eprintln!("{}", "✓ Basin reconfigured".green().bold());
-            println!("{}", json_to_table(&serde_json::to_value(&config)?));
+            output::print_value(output, &config)?;
         }
 
         Command::ListAccessTokens(args) => {
-            let mut tokens = ops::list_access_tokens(&s2, args).await?;
-            while let Some(token_info) = tokens.try_next().await? {
-                let info = AccessTokenInfo::from(token_info);
-                println!("{}", json_to_table(&serde_json::to_value(&info)?));
-            }
+            let tokens = ops::list_access_tokens(&s2, args).await?;
+            let tokens = tokens.and_then(|token| async move {
+                Ok(serde_json::to_value(AccessTokenInfo::from(token))?)
+            });
+            output::print_list(output, tokens, |value| {
+                if output == OutputFormat::Plain {
+                    output::to_plain(value)
+                } else {
+                    json_to_table(value).to_string()
+                }
+            })
+            .await?;
         }
 
         Command::IssueAccessToken(args) => {
             let token = ops::issue_access_token(&s2, args).await?;
-            println!("{}", token);
+            if output.is_structured() {
+                output::print_value(output, &serde_json::json!({ "access_token": token }))?;
+            } else {
+                println!("{}", token);
+            }
         }
 
         Command::RevokeAccessToken { id } => {
@@ -248,73 +346,148 @@ async fn run() -> Result<(), CliError> {
                 "{}",
                 format!("✓ Access token '{}' revoked", id).green().bold()
             );
+            if output.is_structured() {
+                output::print_value(output, &serde_json::json!({ "id": id.to_string() }))?;
+            }
         }
 
         Command::GetAccountMetrics(args) => {
             let metrics = ops::get_account_metrics(&s2, args).await?;
-            print_metrics(&metrics);
+            print_metrics(output, metrics)?;
         }
 
         Command::GetBasinMetrics(args) => {
             let metrics = ops::get_basin_metrics(&s2, args).await?;
-            print_metrics(&metrics);
+            print_metrics(output, metrics)?;
         }
 
         Command::GetStreamMetrics(args) => {
             let metrics = ops::get_stream_metrics(&s2, args).await?;
-            print_metrics(&metrics);
+            print_metrics(output, metrics)?;
         }
 
         Command::ListStreams(args) => {
             let basin_name = args.uri.basin.clone();
-            let mut streams = ops::list_streams(&s2, args).await?;
-            while let Some(stream_info) = streams.try_next().await? {
-                println!("s2://{}/{}", basin_name, stream_info.name);
-            }
+            let streams = ops::list_streams(&s2, args).await?;
+            output::print_list(output, streams.map_ok(StreamInfo::from), |info| {
+                format!("s2://{}/{}", basin_name, info.name)
+            })
+            .await?;
         }
 
         Command::CreateStream(args) => {
-            ops::create_stream(&s2, args).await?;
+            let info = ops::create_stream(&s2, args).await?;
             eprintln!("{}", "✓ Stream created".green().bold());
+            if output.is_structured() {
+                output::print_value(output, &StreamInfo::from(info))?;
+            }
         }
 
-        Command::DeleteStream { uri } => {
-            ops::delete_stream(&s2, uri).await?;
-            eprintln!("{}", "✓ Stream deletion requested".green().bold());
-        }
+        Command::DeleteStream { uri, bulk } => match uri.exact() {
+            Some(uri) => {
+                ops::delete_stream(&s2, uri.clone()).await?;
+                eprintln!("{}", "✓ Stream deletion requested".green().bold());
+                if output.is_structured() {
+                    output::print_value(output, &StreamOutcome { uri, error: None })?;
+                }
+            }
+            None => {
+                if let Some(uris) = bulk::prepare(&s2, &uri, "Delete", &bulk).await? {
+                    bulk::run(uris, &bulk, output, |uri| ops::delete_stream(&s2, uri)).await?;
+                }
+            }
+        },
 
         Command::GetStreamConfig { uri } => {
             let stream_config = ops::get_stream_config(&s2, uri).await?;
             let stream_config: StreamConfig = stream_config.into();
-            println!("{}", json_to_table(&serde_json::to_value(&stream_config)?));
+            output::print_value(output, &stream_config)?;
         }
 
-        Command::ReconfigureStream(args) => {
-            let config = ops::reconfigure_stream(&s2, args).await?;
+        Command::ReconfigureStream(args) => match args.uri.exact() {
+            Some(uri) => {
+                let config = ops::reconfigure_stream(&s2, uri, args.config).await?;
 
-            eprintln!("{}", "✓ Stream reconfigured".green().bold());
-            println!("{}", json_to_table(&serde_json::to_value(&config)?));
-        }
+                eprintln!("{}", "✓ Stream reconfigured".green().bold());
+                output::print_value(output, &config)?;
+            }
+            None => {
+                if let Some(uris) = bulk::prepare(&s2, &args.uri, "Reconfigure", &args.bulk).await?
+                {
+                    bulk::run(uris, &args.bulk, output, |uri| {
+                        ops::reconfigure_stream(&s2, uri, args.config.clone())
+                    })
+                    .await?;
+                }
+            }
+        },
 
         Command::CheckTail { uri } => {
             let tail = ops::check_tail(&s2, uri).await?;
-            println!("{}", format_position(tail.seq_num, tail.timestamp));
+            if output.is_structured() {
+                output::print_value(output, &StreamPosition::from(tail))?;
+            } else {
+                println!("{}", format_position(tail.seq_num, tail.timestamp));
+            }
         }
 
-        Command::Trim(args) => {
-            let trim_point = args.trim_point;
-            let out = ops::trim(&s2, args).await?;
-            eprintln!(
-                "{}",
-                format!(
-                    "✓ [APPENDED] trim to {} // tail: {}",
-                    trim_point,
-                    format_position(out.start.seq_num, out.start.timestamp)
-                )
-                .green()
-                .bold()
-            );
-        }
+        Command::Trim(args) => match args.uri.exact() {
+            Some(uri) => {
+                let trim_point = match args.trim_point {
+                    Some(trim_point) => trim_point,
+                    None => {
+                        let target = ops::trim_target(&s2, uri.clone(), &args).await?;
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "⦿ Trimming {} records before seq_num {}",
+                                target.records, target.trim_point
+                            )
+                            .blue()
+                            .bold()
+                        );
+                        target.trim_point
+                    }
+                };
+                let out = ops::trim(&s2, uri, trim_point, &args).await?;
+                eprintln!(
+                    "{}",
+                    format!(
+                        "✓ [APPENDED] trim to {} // tail: {}",
+                        trim_point,
+                        format_position(out.start.seq_num, out.start.timestamp)
+                    )
+                    .green()
+                    .bold()
+                );
+                if output.is_structured() {
+                    output::print_value(output, &AppendAck::from(out))?;
+                }
+            }
+            None => {
+                if let Some(uris) = bulk::prepare(&s2, &args.uri, "Trim", &args.bulk).await? {
+                    bulk::run(uris, &args.bulk, output, |uri| async {
+                        let trim_point = match args.trim_point {
+                            Some(trim_point) => trim_point,
+                            None => {
+                                let target = ops::trim_target(&s2, uri.clone(), &args).await?;
+                                eprintln!(
+                                    "{}",
+                                    format!(
+                                        "⦿ Trimming {} records from {uri} before seq_num {}",
+                                        target.records, target.trim_point
+                                    )
+                                    .blue()
+                                );
+                                target.trim_point
+                            }
+                        };
+                        ops::trim(&s2, uri, trim_point, &args).await
+                    })
+                    .await?;
+                }
+            }
+        },
 
         Command::Fence(args) => {
             let fencing_token = args.new_fencing_token.clone();
@@ -329,26 +502,63 @@ async fn run() -> Result<(), CliError> {
                 .green()
                 .bold()
             );
+            if output.is_structured() {
+                output::print_value(output, &AppendAck::from(out))?;
+            }
         }
 
-        Command::Append(args) => {
-            let records_in = args
-                .input
-                .reader()
-                .await
-                .map_err(|e| CliError::RecordReaderInit(e.to_string()))?;
-
-            let record_stream: Pin<Box<dyn Stream<Item = _> + Send + Unpin>> = match args.format {
-                RecordFormat::Text => Box::pin(TextFormatter::parse_records(records_in)),
-                RecordFormat::Json => Box::pin(JsonFormatter::parse_records(records_in)),
-                RecordFormat::JsonBase64 => {
-                    Box::pin(JsonBase64Formatter::parse_records(records_in))
+        Command::Append(mut args) => {
+            if let Some(key) = args.shard_by.take() {
+                return append_sharded(&s2, args, key, output).await;
+            }
+            let mut resume = None;
+            if let Some(path) = &args.resume_state {
+                let RecordsIn::File(input) = &args.input else {
+                    return Err(CliError::InvalidArgs(miette::miette!(
+                        "--resume-state requires a file input"
+                    )));
+                };
+                if let RecordFormat::Csv = args.format {
+                    return Err(CliError::InvalidArgs(miette::miette!(
+                        "--resume-state is not supported with the CSV format"
+                    )));
                 }
-            };
+                let tail = ops::check_tail(&s2, args.uri.clone()).await?;
+                let state =
+                    ResumeState::load(path, &args.uri, &input.to_string_lossy(), tail.seq_num)?;
+                if state.offset() > 0 || state.skip() > 0 {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "↻ [RESUMING] from input offset {} at seq_num {}, skipping {} already appended",
+                            state.offset(),
+                            state.seq_num(),
+                            state.skip()
+                        )
+                        .yellow()
+                        .bold()
+                    );
+                }
+                args.match_seq_num = Some(state.match_seq_num());
+                resume = Some(state);
+            }
+
+            let (mut record_stream, input_offset) =
+                parse_append_input(&args, resume.as_ref().map(ResumeState::offset)).await?;
+            if let (Some(state), Some(input_offset)) = (&resume, input_offset) {
+                let pending = state.pending();
+                record_stream = Box::pin(record_stream.skip(state.skip() as usize).inspect(
+                    move |record| {
+                        if record.is_ok() {
+                            pending.push(input_offset.get());
+                        }
+                    },
+                ));
+            }
 
             let acks = ops::append(
                 &s2,
-                record_stream,
+                AppendRecords(record_stream),
                 args.uri,
                 args.fencing_token,
                 args.match_seq_num,
@@ -356,12 +566,16 @@ async fn run() -> Result<(), CliError> {
             );
             let mut acks = std::pin::pin!(acks);
             let mut last_printed_batch_end: Option<u64> = None;
+            let mut batch_acks: Vec<AppendAck> = Vec::new();
 
             loop {
                 select! {
                     ack = acks.next() => {
                         match ack {
                             Some(Ok(ack)) => {
+                                if let Some(state) = &mut resume {
+                                    state.acknowledged(ack.batch.end.seq_num)?;
+                                }
                                 if last_printed_batch_end.is_none_or(|end| end != ack.batch.end.seq_num) {
                                     last_printed_batch_end = Some(ack.batch.end.seq_num);
                                     eprintln!(
@@ -375,6 +589,15 @@ async fn run() -> Result<(), CliError> {
                                         .green()
                                         .bold()
                                     );
+                                    match output {
+                                        OutputFormat::Ndjson => {
+                                            output::print_value(output, &AppendAck::from(ack.batch))?;
+                                        }
+                                        OutputFormat::Json | OutputFormat::Yaml => {
+                                            batch_acks.push(ack.batch.into());
+                                        }
+                                        OutputFormat::Table | OutputFormat::Plain => {}
+                                    }
                                 }
                             }
                             Some(Err(e)) => {
@@ -389,15 +612,84 @@ async fn run() -> Result<(), CliError> {
                     }
                 }
             }
+
+            if !batch_acks.is_empty() {
+                output::print_value(output, &batch_acks)?;
+            }
         }
 
-        Command::Read(args) => {
-            let mut batches = ops::read(&s2, &args).await?;
-            let mut writer = args
-                .output
-                .writer()
-                .await
-                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+        Command::Read(mut args) => {
+            if args.webhook.webhook.is_some()
+                && (args.template.is_some()
+                    || !matches!(args.format, RecordFormat::Json | RecordFormat::JsonBase64))
+            {
+                return Err(CliError::InvalidArgs(miette::miette!(
+                    "--webhook requires --format json or json-base64"
+                )));
+            }
+            let Some(uri) = exact_stream(&args.uris) else {
+                if args.checkpoint.is_some() {
+                    return Err(CliError::InvalidArgs(miette::miette!(
+                        "--checkpoint requires a single stream"
+                    )));
+                }
+                if args.exec.exec {
+                    return Err(CliError::InvalidArgs(miette::miette!(
+                        "--exec requires a single stream"
+                    )));
+                }
+                if args.webhook.webhook.is_some() {
+                    return Err(CliError::InvalidArgs(miette::miette!(
+                        "--webhook requires a single stream"
+                    )));
+                }
+                let mut sources = Vec::new();
+                for uri in bulk::resolve_all(&s2, &args.uris).await? {
+                    let batches = ops::read(&s2, uri.clone(), &args).await?;
+                    sources.push((uri, Box::pin(ops::records(batches)) as merge::RecordStream));
+                }
+                // Without a limit, each read session follows its stream.
+                let follow = args.count.is_none() && args.bytes.is_none() && args.until.is_none();
+                let records =
+                    merge::merge_by_timestamp(sources, follow.then_some(*args.watermark_delay));
+                let mut sink = RecordSink::for_read(&args).await?;
+                sink.write_all(records.map_ok(|(uri, record)| (Some(uri), record)))
+                    .await?;
+                sink.report();
+                return Ok(());
+            };
+
+            let checkpoint = args
+                .checkpoint
+                .as_ref()
+                .map(|path| Checkpoint::new(path, &uri));
+            if let Some(seq_num) = checkpoint
+                .as_ref()
+                .map(Checkpoint::load)
+                .transpose()?
+                .flatten()
+            {
+                eprintln!(
+                    "{}",
+                    format!("↻ [RESUMING] after checkpointed seq_num {seq_num}")
+                        .yellow()
+                        .bold()
+                );
+                args.seq_num = Some(seq_num + 1);
+                args.timestamp = None;
+                args.ago = None;
+                args.tail_offset = None;
+            }
+
+            if args.exec.exec {
+                return exec::run(&s2, uri, &args, checkpoint.as_ref()).await;
+            }
+            if let Some(url) = args.webhook.webhook.clone() {
+                return webhook::run(&s2, uri, url, &args, checkpoint.as_ref()).await;
+            }
+
+            let mut batches = ops::read(&s2, uri, &args).await?;
+            let mut sink = RecordSink::for_read(&args).await?;
 
             loop {
                 select! {
@@ -423,21 +715,13 @@ async fn run() -> Result<(), CliError> {
                                 );
 
                                 for record in &batch.records {
-                                    write_record(record, &mut writer, args.format).await?;
-                                    let skip_newline = matches!(args.format, RecordFormat::Text)
-                                        && record.is_command_record();
-                                    if !skip_newline {
-                                        writer
-                                            .write_all(b"\n")
-                                            .await
-                                            .map_err(|e| CliError::RecordWrite(e.to_string()))?;
-                                    }
+                                    sink.write(record, None).await?;
                                 }
 
-                                writer
-                                    .flush()
-                                    .await
-                                    .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+                                sink.flush().await?;
+                                if let Some(checkpoint) = &checkpoint {
+                                    checkpoint.save(*seq_range.end())?;
+                                }
                             }
                             Some(Err(e)) => {
                                 return Err(CliError::op(OpKind::Read, e));
@@ -451,45 +735,193 @@ async fn run() -> Result<(), CliError> {
                     }
                 }
             }
+            sink.report();
         }
 
         Command::Tail(args) => {
-            let mut records = ops::tail(&s2, &args).await?;
-            let mut writer = args
-                .output
-                .writer()
-                .await
-                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+            let mut sink = RecordSink::for_tail(&args).await?;
+            if let Some(uri) = exact_stream(&args.uris) {
+                let records = ops::tail(&s2, uri, &args).await?;
+                sink.write_all(records.map_ok(|record| (None, record)))
+                    .await?;
+            } else {
+                let mut sources = Vec::new();
+                for uri in bulk::resolve_all(&s2, &args.uris).await? {
+                    let records = ops::tail(&s2, uri.clone(), &args).await?;
+                    sources.push((uri, records));
+                }
+                let records = merge::merge_by_timestamp(
+                    sources,
+                    args.follow.then_some(*args.watermark_delay),
+                );
+                sink.write_all(records.map_ok(|(uri, record)| (Some(uri), record)))
+                    .await?;
+            }
+            sink.report();
+        }
 
-            loop {
-                select! {
-                    record = records.next() => {
-                        match record {
-                            Some(Ok(record)) => {
-                                write_record(&record, &mut writer, args.format).await?;
-                                writer
-                                    .write_all(b"\n")
-                                    .await
-                                    .map_err(|e| CliError::RecordWrite(e.to_string()))?;
-                                writer
-                                    .flush()
-                                    .await
-                                    .map_err(|e| CliError::RecordWrite(e.to_string()))?;
-                            }
-                            Some(Err(e)) => {
-                                return Err(e);
-                            }
-                            None => break,
-                        }
-                    }
-                    _ = tokio::signal::ctrl_c() => {
-                        eprintln!("{}", "■ [ABORTED]".red().bold());
-                        break;
-                    }
+        Command::Stats(args) => {
+            let stats = stats::run(&s2, args).await?;
+            if output.is_structured() {
+                output::print_value(output, &stats)?;
+            } else {
+                stats::print(&stats);
+            }
+        }
+
+        Command::Cp(args) => {
+            let summary = copy::run(&s2, args).await?;
+            if output.is_structured() {
+                output::print_value(output, &summary)?;
+            } else {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "✓ [DONE] copied {} records from seq_num range {}..{}",
+                        summary.records, summary.src_start, summary.src_end
+                    )
+                    .green()
+                    .bold()
+                );
+            }
+        }
+
+        Command::Mirror(args) => {
+            mirror::run(&s2, args).await?;
+        }
+
+        Command::Export(args) => {
+            let out = args.out.clone();
+            let Some(manifest) = archive::export(&s2, args).await? else {
+                return Ok(());
+            };
+            if output.is_structured() {
+                output::print_value(output, &manifest)?;
+            } else {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "✓ [EXPORTED] {} records from {} to {}",
+                        manifest.records,
+                        manifest.stream,
+                        out.display()
+                    )
+                    .green()
+                    .bold()
+                );
+            }
+        }
+
+        Command::Import(args) => {
+            let uri = args.uri.clone();
+            let summary = archive::import(&s2, args).await?;
+            if output.is_structured() {
+                output::print_value(output, &summary)?;
+            } else if summary.aborted {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "■ [PARTIAL] imported {} of {} records into {uri}",
+                        summary.records, summary.archive_records
+                    )
+                    .red()
+                    .bold()
+                );
+            } else {
+                eprintln!(
+                    "{}",
+                    format!("✓ [DONE] imported {} records into {uri}", summary.records)
+                        .green()
+                        .bold()
+                );
+            }
+        }
+
+        Command::Plan(args) => {
+            let manifest = plan::Manifest::load(&args.file)?;
+            let actions = plan::plan(&s2, &manifest, args.prune).await?;
+            if output.is_structured() {
+                output::print_value(output, &actions)?;
+            } else if actions.is_empty() {
+                eprintln!("{}", "✓ No changes".green().bold());
+            } else {
+                for action in &actions {
+                    println!("{}", action.describe());
                 }
             }
         }
 
+        Command::Apply(args) => {
+            let manifest = plan::Manifest::load(&args.file)?;
+            let actions = plan::plan(&s2, &manifest, args.prune).await?;
+            if actions.is_empty() && !output.is_structured() {
+                eprintln!("{}", "✓ No changes".green().bold());
+            }
+            for action in &actions {
+                if !output.is_structured() {
+                    println!("{}", action.describe());
+                }
+                plan::apply(&s2, action).await?;
+            }
+            if output.is_structured() {
+                output::print_value(output, &actions)?;
+            } else if !actions.is_empty() {
+                eprintln!(
+                    "{}",
+                    format!("✓ [APPLIED] {} changes", actions.len())
+                        .green()
+                        .bold()
+                );
+            }
+        }
+
+        Command::DumpConfig(args) => {
+            let manifest = plan::Manifest::dump(&s2, &args.basin.into()).await?;
+            print!("{}", manifest.render(args.format)?);
+        }
+
+        Command::Lock(LockCommand::Acquire(args)) => {
+            let lease = lock::acquire(&s2, &args).await?;
+            eprintln!(
+                "{}",
+                format!("✓ [ACQUIRED] {} as \"{}\"", args.uri, lease.holder)
+                    .green()
+                    .bold()
+            );
+            if output.is_structured() {
+                output::print_value(output, &lease)?;
+            } else {
+                println!("{}", lease.token);
+            }
+        }
+
+        Command::Lock(LockCommand::Renew(args)) => {
+            let lease = lock::renew(&s2, &args).await?;
+            eprintln!("{}", format!("✓ [RENEWED] {}", args.uri).green().bold());
+            if output.is_structured() {
+                output::print_value(output, &lease)?;
+            }
+        }
+
+        Command::Lock(LockCommand::Release(args)) => {
+            let lease = lock::release(&s2, &args).await?;
+            eprintln!("{}", format!("✓ [RELEASED] {}", args.uri).green().bold());
+            if output.is_structured() {
+                output::print_value(output, &lease)?;
+            }
+        }
+
+        Command::Lock(LockCommand::Run(args)) => {
+            let status = lock::run(&s2, args).await?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+
+        Command::Serve(args) => {
+            serve::run(s2.clone(), args).await?;
+        }
+
         Command::Bench(args) => {
             let basin_name = args.basin.0.clone();
             let stream_name: StreamName = format!("bench/{}", uuid::Uuid::new_v4())
@@ -555,11 +987,12 @@ async fn run() -> Result<(), CliError> {
     Ok(())
 }
 
-fn format_basin_state(state: BasinState) -> colored::ColoredString {
+fn format_basin_state(state: &str) -> colored::ColoredString {
     match state {
-        BasinState::Active => "active".green(),
-        BasinState::Creating => "creating".yellow(),
-        BasinState::Deleting => "deleting".red(),
+        "active" => state.green(),
+        "creating" => state.yellow(),
+        "deleting" => state.red(),
+        _ => state.normal(),
     }
 }
 
@@ -567,55 +1000,143 @@ fn format_position(seq_num: u64, timestamp: u64) -> String {
     format!("{seq_num} @ {timestamp}")
 }
 
-async fn write_record(
-    record: &s2_sdk::types::SequencedRecord,
-    writer: &mut (impl tokio::io::AsyncWrite + Unpin),
-    format: RecordFormat,
+type ParsedRecordStream =
+    Pin<Box<dyn Stream<Item = Result<ParsedRecord, RecordParseError>> + Send + Unpin>>;
+
+/// Parse the append input, starting at `resume_offset` and tracking the input offset if given.
+async fn parse_append_input(
+    args: &AppendArgs,
+    resume_offset: Option<u64>,
+) -> Result<(ParsedRecordStream, Option<InputOffset>), CliError> {
+    let init_error = |e: std::io::Error| CliError::RecordReaderInit(e.to_string());
+    let (mut record_stream, offset): (ParsedRecordStream, _) = match args.format {
+        RecordFormat::Binary => {
+            let (bytes_in, offset) = match resume_offset {
+                Some(start) => {
+                    let (reader, offset) =
+                        args.input.byte_reader_at(start).await.map_err(init_error)?;
+                    (reader, Some(offset))
+                }
+                None => (args.input.byte_reader().await.map_err(init_error)?, None),
+            };
+            (Box::pin(BinaryFormatter::parse(bytes_in)), offset)
+        }
+        format => {
+            let (records_in, offset) = match resume_offset {
+                Some(start) => {
+                    let (reader, offset) = args
+                        .input
+                        .reader_at(&args.delimiter, start)
+                        .await
+                        .map_err(init_error)?;
+                    (reader, Some(offset))
+                }
+                None => (
+                    args.input
+                        .reader(&args.delimiter)
+                        .await
+                        .map_err(init_error)?,
+                    None,
+                ),
+            };
+            let record_stream: ParsedRecordStream = match format {
+                RecordFormat::Text => Box::pin(TextFormatter::parse(records_in)),
+                RecordFormat::Json => Box::pin(JsonFormatter::parse(records_in)),
+                RecordFormat::JsonBase64 => Box::pin(JsonBase64Formatter::parse(records_in)),
+                RecordFormat::Csv => Box::pin(CsvFormatter::parse(records_in)),
+                RecordFormat::Binary => unreachable!("binary input is not line-delimited"),
+            };
+            (record_stream, offset)
+        }
+    };
+    if !args.append_commands {
+        record_stream =
+            Box::pin(record_stream.try_filter(|record| future::ready(!record.is_command_record())));
+    }
+    Ok((record_stream, offset))
+}
+
+/// Append records across the shards of `args.uri`, routed by `key`.
+async fn append_sharded(
+    s2: &S2,
+    args: AppendArgs,
+    key: ShardKey,
+    output: OutputFormat,
 ) -> Result<(), CliError> {
-    match format {
-        RecordFormat::Text => {
-            if record.is_command_record() {
-                if let Some(header) = record.headers.first() {
-                    let cmd_type = &header.value;
-                    let cmd_desc = if cmd_type.as_ref() == b"fence" {
-                        let fencing_token = String::from_utf8_lossy(&record.body);
-                        format!("new fencing token \"{}\"", fencing_token)
-                    } else if cmd_type.as_ref() == b"trim" {
-                        let trim_point = if record.body.len() >= 8 {
-                            u64::from_be_bytes(record.body[..8].try_into().unwrap_or_default())
-                        } else {
-                            0
+    let shards =
+        shard::expand(&args.uri).map_err(|e| CliError::InvalidArgs(miette::miette!("{e}")))?;
+    let (record_stream, _) = parse_append_input(&args, None).await?;
+    let acks = shard::append(
+        s2,
+        record_stream,
+        &shards,
+        key,
+        args.fencing_token,
+        *args.linger,
+    );
+    let mut acks = std::pin::pin!(acks);
+    let mut last_printed_batch_ends: Vec<Option<u64>> = vec![None; shards.len()];
+    let mut shard_acks: Vec<ShardAppendAck> = Vec::new();
+
+    loop {
+        select! {
+            ack = acks.next() => {
+                match ack {
+                    Some(Ok((shard, ack))) => {
+                        let last_printed_batch_end = &mut last_printed_batch_ends[shard];
+                        if last_printed_batch_end.is_some_and(|end| end == ack.batch.end.seq_num) {
+                            continue;
+                        }
+                        *last_printed_batch_end = Some(ack.batch.end.seq_num);
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "✓ [APPENDED] {} {}..{} // tail: {}",
+                                shards[shard],
+                                ack.batch.start.seq_num,
+                                ack.batch.end.seq_num,
+                                format_position(ack.batch.tail.seq_num, ack.batch.tail.timestamp)
+                            )
+                            .green()
+                            .bold()
+                        );
+                        let ack = ShardAppendAck {
+                            stream: shards[shard].to_string(),
+                            ack: ack.batch.into(),
                         };
-                        format!("trim to {}", trim_point)
-                    } else {
-                        "unknown command".to_string()
-                    };
-                    eprintln!(
-                        "{} // {}",
-                        cmd_desc.bold(),
-                        format_position(record.seq_num, record.timestamp)
-                    );
+                        match output {
+                            OutputFormat::Ndjson => output::print_value(output, &ack)?,
+                            OutputFormat::Json | OutputFormat::Yaml => shard_acks.push(ack),
+                            OutputFormat::Table | OutputFormat::Plain => {}
+                        }
+                    }
+                    Some(Err(e)) => {
+                        return Err(e);
+                    }
+                    None => break,
                 }
-            } else {
-                TextFormatter::write_record(record, writer)
-                    .await
-                    .map_err(|e| CliError::RecordWrite(e.to_string()))?;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                break;
             }
         }
-        RecordFormat::Json => {
-            JsonFormatter::write_record(record, writer)
-                .await
-                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
-        }
-        RecordFormat::JsonBase64 => {
-            JsonBase64Formatter::write_record(record, writer)
-                .await
-                .map_err(|e| CliError::RecordWrite(e.to_string()))?;
-        }
+    }
+
+    if !shard_acks.is_empty() {
+        output::print_value(output, &shard_acks)?;
     }
     Ok(())
 }
 
+/// The stream to read, if `uris` is a single stream rather than a pattern.
+fn exact_stream(uris: &[S2BasinAndStreamSelector]) -> Option<S2BasinAndStreamUri> {
+    match uris {
+        [selector] => selector.exact(),
+        _ => None,
+    }
+}
+
 fn format_timestamp(ts: u32) -> String {
     use std::time::{Duration, UNIX_EPOCH};
     let time = UNIX_EPOCH + Duration::from_secs(ts as u64);
@@ -629,7 +1150,12 @@ fn format_unit(unit: s2_sdk::types::MetricUnit) -> &'static str {
     }
 }
 
-fn print_metrics(metrics: &[Metric]) {
+fn print_metrics(output: OutputFormat, metrics: Vec<Metric>) -> Result<(), CliError> {
+    if output != OutputFormat::Table {
+        let metrics: Vec<types::Metric> = metrics.into_iter().map(Into::into).collect();
+        return output::print_value(output, &metrics);
+    }
+
     #[derive(Tabled)]
     struct AccumulationRow {
         interval_start: String,
@@ -642,7 +1168,7 @@ fn print_metrics(metrics: &[Metric]) {
         value: String,
     }
 
-    for metric in metrics {
+    for metric in &metrics {
         match metric {
             Metric::Scalar(m) => {
                 println!("{}: {} {}", m.name, m.value, format_unit(m.unit));
@@ -714,4 +1240,5 @@ fn print_metrics(metrics: &[Metric]) {
             }
         }
     }
+    Ok(())
 }
@@ -0,0 +1,295 @@
+//! Merging records from several streams in timestamp order.
+//!
+//! Streams that end are merged exactly, pulling only from the stream with the
+//! earliest next record.
+//!
+//! Streams that are followed may never end, so their records are instead
+//! buffered until every other stream has either caught up to their timestamp,
+//! ended, or been quiet for the watermark delay. A stream that resumes after
+//! being quiet may therefore emit records older than ones already released.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    pin::Pin,
+    time::Duration,
+};
+
+use futures::{Stream, StreamExt, TryStreamExt, future, stream};
+use s2_sdk::types::SequencedRecord;
+use tokio::{select, time::Instant};
+
+use crate::{error::CliError, types::S2BasinAndStreamUri};
+
+pub type RecordStream = Pin<Box<dyn Stream<Item = Result<SequencedRecord, CliError>> + Send>>;
+
+struct Pending<T> {
+    timestamp: u64,
+    source: usize,
+    seq_num: u64,
+    item: T,
+}
+
+impl<T> Pending<T> {
+    fn key(&self) -> (u64, usize, u64) {
+        (self.timestamp, self.source, self.seq_num)
+    }
+}
+
+impl<T> PartialEq for Pending<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<T> Eq for Pending<T> {}
+
+impl<T> PartialOrd for Pending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Pending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+struct Source {
+    /// Latest timestamp received.
+    latest: Option<u64>,
+    last_heard: Instant,
+    done: bool,
+}
+
+/// Orders items from several sources by timestamp, holding back each item
+/// until no active source can still produce an earlier one.
+struct Merger<T> {
+    pending: BinaryHeap<Reverse<Pending<T>>>,
+    sources: Vec<Source>,
+    delay: Duration,
+}
+
+impl<T> Merger<T> {
+    fn new(sources: usize, delay: Duration, now: Instant) -> Self {
+        Self {
+            pending: BinaryHeap::new(),
+            sources: (0..sources)
+                .map(|_| Source {
+                    latest: None,
+                    last_heard: now,
+                    done: false,
+                })
+                .collect(),
+            delay,
+        }
+    }
+
+    fn push(&mut self, source: usize, timestamp: u64, seq_num: u64, item: T, now: Instant) {
+        let state = &mut self.sources[source];
+        state.latest = Some(state.latest.map_or(timestamp, |ts| ts.max(timestamp)));
+        state.last_heard = now;
+        self.pending.push(Reverse(Pending {
+            timestamp,
+            source,
+            seq_num,
+            item,
+        }));
+    }
+
+    fn finish(&mut self, source: usize) {
+        self.sources[source].done = true;
+    }
+
+    /// Whether all sources ended and all items were released.
+    fn is_drained(&self) -> bool {
+        self.pending.is_empty() && self.sources.iter().all(|s| s.done)
+    }
+
+    /// Release the earliest item, if no active source can precede it.
+    fn pop_ready(&mut self, now: Instant) -> Option<(usize, T)> {
+        let Reverse(head) = self.pending.peek()?;
+        // A source that has not produced anything yet holds back everything.
+        let watermark = self
+            .sources
+            .iter()
+            .filter(|s| !s.done && now.duration_since(s.last_heard) < self.delay)
+            .map(|s| s.latest)
+            .min();
+        let ready = match watermark {
+            None => true,
+            Some(None) => false,
+            Some(Some(watermark)) => head.timestamp <= watermark,
+        };
+        if !ready {
+            return None;
+        }
+        let Reverse(head) = self.pending.pop().expect("peeked");
+        Some((head.source, head.item))
+    }
+}
+
+/// Merge items from sources that each end, tagging each with the index of its source.
+///
+/// Only the source with the earliest head, by the `(timestamp, seq_num)` from `key`,
+/// is polled for its next item, so no source runs ahead of the others.
+fn merge_sorted<T, E, S>(
+    sources: Vec<S>,
+    key: impl Fn(&T) -> (u64, u64),
+) -> impl Stream<Item = Result<(usize, T), E>>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+{
+    async_stream::try_stream! {
+        let mut sources = sources;
+        let mut heads = BinaryHeap::new();
+        let firsts = future::join_all(sources.iter_mut().map(StreamExt::next)).await;
+        for (source, item) in firsts.into_iter().enumerate() {
+            if let Some(item) = item {
+                let item = item?;
+                let (timestamp, seq_num) = key(&item);
+                heads.push(Reverse(Pending { timestamp, source, seq_num, item }));
+            }
+        }
+        while let Some(Reverse(head)) = heads.pop() {
+            let source = head.source;
+            yield (source, head.item);
+            if let Some(item) = sources[source].next().await {
+                let item = item?;
+                let (timestamp, seq_num) = key(&item);
+                heads.push(Reverse(Pending { timestamp, source, seq_num, item }));
+            }
+        }
+    }
+}
+
+/// Merge records from several streams in timestamp order, tagging each with its stream.
+///
+/// Streams that end are merged exactly. When following streams, pass a
+/// `watermark_delay` for how long a quiet stream holds back records from the others.
+pub fn merge_by_timestamp(
+    sources: Vec<(S2BasinAndStreamUri, RecordStream)>,
+    watermark_delay: Option<Duration>,
+) -> impl Stream<Item = Result<(S2BasinAndStreamUri, SequencedRecord), CliError>> + Send {
+    let (uris, streams): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+    match watermark_delay {
+        Some(delay) => merge_with_watermark(uris, streams, delay).left_stream(),
+        None => merge_sorted(streams, |record: &SequencedRecord| {
+            (record.timestamp, record.seq_num)
+        })
+        .map_ok(move |(i, record)| (uris[i].clone(), record))
+        .right_stream(),
+    }
+}
+
+fn merge_with_watermark(
+    uris: Vec<S2BasinAndStreamUri>,
+    streams: Vec<RecordStream>,
+    delay: Duration,
+) -> impl Stream<Item = Result<(S2BasinAndStreamUri, SequencedRecord), CliError>> + Send {
+    let mut merged = stream::select_all(streams.into_iter().enumerate().map(|(i, records)| {
+        records
+            .map(move |record| (i, Some(record)))
+            .chain(stream::once(async move { (i, None) }))
+            .boxed()
+    }));
+
+    async_stream::try_stream! {
+        let mut merger = Merger::new(uris.len(), delay, Instant::now());
+        let mut ticks = tokio::time::interval((delay / 4).max(Duration::from_millis(10)));
+        loop {
+            while let Some((i, record)) = merger.pop_ready(Instant::now()) {
+                yield (uris[i].clone(), record);
+            }
+            if merger.is_drained() {
+                break;
+            }
+            // Ticks re-check for streams that went quiet.
+            let next = select! {
+                next = merged.next() => next,
+                _ = ticks.tick() => continue,
+            };
+            match next {
+                Some((i, Some(record))) => {
+                    let record = record?;
+                    merger.push(i, record.timestamp, record.seq_num, record, Instant::now());
+                }
+                Some((i, None)) => merger.finish(i),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{TryStreamExt, stream};
+    use tokio::time::Instant;
+
+    use super::{Merger, merge_sorted};
+
+    fn drain(merger: &mut Merger<&'static str>, now: Instant) -> Vec<&'static str> {
+        std::iter::from_fn(|| merger.pop_ready(now).map(|(_, item)| item)).collect()
+    }
+
+    #[test]
+    fn test_merger_orders_by_timestamp() {
+        let now = Instant::now();
+        let mut merger = Merger::new(2, Duration::from_secs(1), now);
+
+        merger.push(0, 10, 0, "a10", now);
+        merger.push(0, 30, 1, "a30", now);
+        // Source 1 has not produced anything yet.
+        assert!(drain(&mut merger, now).is_empty());
+
+        merger.push(1, 20, 0, "b20", now);
+        assert_eq!(drain(&mut merger, now), vec!["a10", "b20"]);
+
+        merger.finish(1);
+        assert_eq!(drain(&mut merger, now), vec!["a30"]);
+        assert!(!merger.is_drained());
+        merger.finish(0);
+        assert!(merger.is_drained());
+    }
+
+    #[tokio::test]
+    async fn test_merge_sorted() {
+        let source =
+            |items: Vec<(u64, u64, &'static str)>| stream::iter(items.into_iter().map(Ok::<_, ()>));
+        let merged: Vec<_> = merge_sorted(
+            vec![
+                source(vec![(10, 0, "a10"), (30, 1, "a30"), (40, 2, "a40")]),
+                source(vec![]),
+                source(vec![(20, 0, "c20"), (30, 1, "c30")]),
+            ],
+            |&(timestamp, seq_num, _)| (timestamp, seq_num),
+        )
+        .map_ok(|(_, (_, _, item))| item)
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(merged, vec!["a10", "c20", "a30", "c30", "a40"]);
+    }
+
+    #[test]
+    fn test_merger_releases_after_delay() {
+        let start = Instant::now();
+        let mut merger = Merger::new(2, Duration::from_secs(1), start);
+
+        merger.push(0, 10, 0, "a10", start);
+        assert!(drain(&mut merger, start).is_empty());
+
+        // Source 1 stays quiet for longer than the delay, so it no longer holds back source 0.
+        let later = start + Duration::from_millis(1500);
+        merger.push(0, 20, 1, "a20", later);
+        assert_eq!(drain(&mut merger, later), vec!["a10", "a20"]);
+
+        // Ties are broken by source.
+        merger.push(1, 30, 0, "b30", later);
+        merger.push(0, 30, 2, "a30", later);
+        assert_eq!(drain(&mut merger, later), vec!["a30", "b30"]);
+    }
+}
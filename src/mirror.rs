@@ -0,0 +1,279 @@
+use colored::Colorize;
+use futures::{StreamExt, TryStreamExt, future};
+use s2_sdk::{
+    S2,
+    types::{FencingToken, Header, ReadFrom, ReadInput, ReadLimits, ReadStart, ReadStop, S2Error},
+};
+use tokio::select;
+
+use crate::{
+    cli::{FenceArgs, MirrorArgs},
+    copy::to_append_record,
+    error::CliError,
+    ops,
+    types::{S2BasinAndStreamUri, backoff},
+};
+
+/// Header added to mirrored records, holding the source sequence number as decimal.
+const MIRROR_SEQ_NUM_HEADER: &str = "s2-mirror-seq-num";
+
+/// Number of destination records to scan at a time for the last mirrored record.
+const SCAN_WINDOW: u64 = 1024;
+
+/// Server error codes of 408, 429 and 5xx responses, which `webhook` also retries.
+const TRANSIENT_ERROR_CODES: [&str; 7] = [
+    "request_timeout",
+    "rate_limited",
+    "other",
+    "storage",
+    "hot_server",
+    "unavailable",
+    "upstream_timeout",
+];
+
+/// Prefixes of client errors from timeouts and failed connections.
+const TRANSIENT_CLIENT_ERRORS: [&str; 8] = [
+    "connect:",
+    "timeout:",
+    "connection closed early:",
+    "request canceled:",
+    "unexpected eof:",
+    "connection reset:",
+    "connection aborted:",
+    "connection refused:",
+];
+
+/// Source sequence number of a mirrored record. A record mirrored along a chain
+/// of streams keeps the header from its last hop.
+fn mirrored_seq_num(headers: &[Header]) -> Option<u64> {
+    headers
+        .iter()
+        .rev()
+        .find(|h| h.name.as_ref() == MIRROR_SEQ_NUM_HEADER.as_bytes())
+        .and_then(|h| std::str::from_utf8(&h.value).ok()?.parse().ok())
+}
+
+/// Find the source sequence number of the last record mirrored into `dst`,
+/// scanning backwards from `tail` one window at a time.
+async fn last_mirrored_seq_num(
+    s2: &S2,
+    dst: &S2BasinAndStreamUri,
+    tail: u64,
+) -> Result<Option<u64>, CliError> {
+    let mut end = tail;
+    while end > 0 {
+        let start = end.saturating_sub(SCAN_WINDOW);
+        let batches = ops::read_session(
+            s2,
+            dst.clone(),
+            ReadInput::new()
+                .with_start(ReadStart::new().with_from(ReadFrom::SeqNum(start)))
+                .with_stop(
+                    ReadStop::new()
+                        .with_limits(ReadLimits::new().with_count((end - start) as usize)),
+                ),
+        )
+        .await?;
+        let last = ops::records(batches)
+            .try_fold(None, |last, record| {
+                future::ready(Ok(mirrored_seq_num(&record.headers).or(last)))
+            })
+            .await?;
+        if last.is_some() {
+            return Ok(last);
+        }
+        end = start;
+    }
+    Ok(None)
+}
+
+/// Whether mirroring can resume after `e`. Failed append conditions mean that
+/// another instance has taken over the destination, so they are not retried.
+fn is_transient(e: &CliError) -> bool {
+    match e {
+        CliError::Operation(_, S2Error::Server(response)) => {
+            TRANSIENT_ERROR_CODES.contains(&response.code.as_str())
+        }
+        CliError::Operation(_, S2Error::Client(msg)) => is_transient_client_error(msg),
+        _ => false,
+    }
+}
+
+fn is_transient_client_error(msg: &str) -> bool {
+    TRANSIENT_CLIENT_ERRORS
+        .iter()
+        .any(|prefix| msg.starts_with(prefix))
+}
+
+/// Mirror from the last mirrored record until the source ends or an error occurs,
+/// setting `progressed` once a record has been mirrored.
+async fn mirror(
+    s2: &S2,
+    args: &MirrorArgs,
+    fencing_token: &FencingToken,
+    progressed: &mut bool,
+) -> Result<(), CliError> {
+    let dst_tail = ops::check_tail(s2, args.dst.clone()).await?.seq_num;
+    let start = match last_mirrored_seq_num(s2, &args.dst, dst_tail).await? {
+        Some(seq_num) => seq_num + 1,
+        None => args.seq_num.unwrap_or(0),
+    };
+    eprintln!(
+        "{}",
+        format!(
+            "⦿ [MIRRORING] {} from seq_num {start} to {} // fencing token: \"{fencing_token}\"",
+            args.src, args.dst
+        )
+        .blue()
+        .bold()
+    );
+
+    let batches = ops::read_session(
+        s2,
+        args.src.clone(),
+        ReadInput::new().with_start(ReadStart::new().with_from(ReadFrom::SeqNum(start))),
+    )
+    .await?;
+
+    let preserve_timestamps = args.preserve_timestamps;
+    let records = ops::records(batches)
+        .try_filter(|record| future::ready(!record.is_command_record()))
+        .map(move |record| {
+            let mut record = record?;
+            record
+                .headers
+                .retain(|h| h.name.as_ref() != MIRROR_SEQ_NUM_HEADER.as_bytes());
+            record.headers.push(Header::new(
+                MIRROR_SEQ_NUM_HEADER,
+                record.seq_num.to_string(),
+            ));
+            to_append_record(record, preserve_timestamps)
+        });
+
+    let acks = ops::append(
+        s2,
+        Box::pin(records),
+        args.dst.clone(),
+        Some(fencing_token.clone()),
+        Some(dst_tail),
+        *args.linger,
+    );
+    let mut acks = std::pin::pin!(acks);
+    let mut last_printed_batch_end: Option<u64> = None;
+
+    while let Some(ack) = acks.try_next().await? {
+        *progressed = true;
+        if last_printed_batch_end.is_none_or(|end| end != ack.batch.end.seq_num) {
+            last_printed_batch_end = Some(ack.batch.end.seq_num);
+            eprintln!(
+                "{}",
+                format!(
+                    "✓ [MIRRORED] {}..{} to {}",
+                    ack.batch.start.seq_num, ack.batch.end.seq_num, args.dst
+                )
+                .green()
+                .bold()
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(s2: &S2, args: MirrorArgs) -> Result<(), CliError> {
+    // Fence first, so that any previous instance can no longer append
+    // once the destination has been inspected.
+    let fencing_token = FencingToken::generate(16).expect("valid fencing token");
+    ops::fence(
+        s2,
+        FenceArgs {
+            uri: args.dst.clone(),
+            new_fencing_token: fencing_token.clone(),
+            fencing_token: None,
+            match_seq_num: None,
+        },
+    )
+    .await?;
+
+    let mut attempt = 1;
+    loop {
+        let mut progressed = false;
+        let result = select! {
+            result = mirror(s2, &args, &fencing_token, &mut progressed) => result,
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                return Ok(());
+            }
+        };
+        let e = match result {
+            Ok(()) => return Ok(()),
+            Err(e) if is_transient(&e) => e,
+            Err(e) => return Err(e),
+        };
+
+        if progressed {
+            attempt = 1;
+        }
+        if attempt >= args.retry_attempts.get() {
+            return Err(e);
+        }
+        let delay = backoff(*args.retry_backoff, attempt);
+        eprintln!(
+            "{}",
+            format!("↻ [RETRYING] in {}: {e}", humantime::format_duration(delay))
+                .yellow()
+                .bold()
+        );
+        select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                return Ok(());
+            }
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use s2_sdk::types::{Header, S2Error};
+
+    use super::{MIRROR_SEQ_NUM_HEADER, TRANSIENT_ERROR_CODES, is_transient, mirrored_seq_num};
+    use crate::error::{CliError, OpKind};
+
+    #[test]
+    fn test_mirrored_seq_num() {
+        assert_eq!(mirrored_seq_num(&[Header::new("other", "1")]), None);
+        // Mirrored from a, then from b, so b's sequence number counts.
+        let headers = [
+            Header::new(MIRROR_SEQ_NUM_HEADER, "7"),
+            Header::new("other", "1"),
+            Header::new(MIRROR_SEQ_NUM_HEADER, "42"),
+        ];
+        assert_eq!(mirrored_seq_num(&headers), Some(42));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let client = |msg: &str| CliError::op(OpKind::Read, S2Error::Client(msg.to_owned()));
+        assert!(is_transient(&client("connect: error trying to connect")));
+        assert!(is_transient(&client("timeout: operation timed out")));
+        assert!(is_transient(&client("connection reset: reset by peer")));
+        assert!(!is_transient(&client("invalid URL")));
+        assert!(!is_transient(&CliError::RecordWrite(
+            "broken pipe".to_owned()
+        )));
+
+        for code in ["rate_limited", "unavailable", "upstream_timeout", "storage"] {
+            assert!(TRANSIENT_ERROR_CODES.contains(&code));
+        }
+        for code in [
+            "stream_not_found",
+            "basin_not_found",
+            "permission_denied",
+            "invalid",
+        ] {
+            assert!(!TRANSIENT_ERROR_CODES.contains(&code));
+        }
+    }
+}
@@ -14,7 +14,7 @@ use s2_sdk::{
         GetStreamMetricsInput, IssueAccessTokenInput, ListAccessTokensInput,
         ListAllAccessTokensInput, ListAllBasinsInput, ListAllStreamsInput, ListBasinsInput,
         ListStreamsInput, MeteredBytes, Metric, ReadBatch, ReadFrom, ReadInput, ReadLimits,
-        ReadStart, ReadStop, ReconfigureBasinInput, ReconfigureStreamInput, S2DateTime,
+        ReadStart, ReadStop, ReconfigureBasinInput, ReconfigureStreamInput, S2DateTime, S2Error,
         SequencedRecord, StreamInfo, StreamMetricSet, StreamPosition, StreamReconfiguration,
         Streaming, TimeRange, TimeRangeAndInterval,
     },
@@ -23,8 +23,7 @@ use s2_sdk::{
 use crate::cli::{
     CreateBasinArgs, CreateStreamArgs, FenceArgs, GetAccountMetricsArgs, GetBasinMetricsArgs,
     GetStreamMetricsArgs, IssueAccessTokenArgs, ListAccessTokensArgs, ListBasinsArgs,
-    ListStreamsArgs, ReadArgs, ReconfigureBasinArgs, ReconfigureStreamArgs, TailArgs,
-    TimeRangeArgs, TrimArgs,
+    ListStreamsArgs, ReadArgs, ReconfigureBasinArgs, TailArgs, TimeRangeArgs, TrimArgs,
 };
 use crate::error::{CliError, OpKind};
 use crate::types::{BasinConfig, Interval, S2BasinAndStreamUri, StreamConfig};
@@ -367,13 +366,14 @@ pub async fn get_stream_config(
 
 pub async fn reconfigure_stream(
     s2: &S2,
-    args: ReconfigureStreamArgs,
+    uri: S2BasinAndStreamUri,
+    config: StreamConfig,
 ) -> Result<StreamConfig, CliError> {
-    let basin = s2.basin(args.uri.basin);
+    let basin = s2.basin(uri.basin);
 
-    let reconfig: StreamReconfiguration = args.config.into();
+    let reconfig: StreamReconfiguration = config.into();
     let config = basin
-        .reconfigure_stream(ReconfigureStreamInput::new(args.uri.stream, reconfig))
+        .reconfigure_stream(ReconfigureStreamInput::new(uri.stream, reconfig))
         .await
         .map_err(|e| CliError::op(OpKind::ReconfigureStream, e))?;
 
@@ -388,12 +388,83 @@ pub async fn check_tail(s2: &S2, uri: S2BasinAndStreamUri) -> Result<StreamPosit
         .map_err(|e| CliError::op(OpKind::CheckTail, e))
 }
 
-pub async fn trim(s2: &S2, args: TrimArgs) -> Result<AppendAck, CliError> {
-    let stream = s2.basin(args.uri.basin).stream(args.uri.stream);
+/// Sequence number a trim resolves to, and how many records it would trim.
+#[derive(Debug, Clone, Copy)]
+pub struct TrimTarget {
+    pub trim_point: u64,
+    pub records: u64,
+}
+
+/// Sequence number of the first record at or after `from`, or the tail if there is none.
+async fn first_seq_num_from(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    from: ReadFrom,
+    tail: u64,
+) -> Result<u64, CliError> {
+    let first = async {
+        let batches = read_session(
+            s2,
+            uri,
+            ReadInput::new()
+                .with_start(ReadStart::new().with_from(from).with_clamp_to_tail(true))
+                .with_stop(ReadStop::new().with_limits(ReadLimits::new().with_count(1))),
+        )
+        .await?;
+        std::pin::pin!(records(batches)).try_next().await
+    };
+    match first.await {
+        Ok(first) => Ok(first.map_or(tail, |record| record.seq_num)),
+        Err(CliError::Operation(_, S2Error::ReadUnwritten(tail))) => Ok(tail.seq_num),
+        Err(e) => Err(e),
+    }
+}
+
+/// Resolve the trim point from a timestamp, age, or count to keep.
+///
+/// An explicit trim point needs no resolution and should be passed to [`trim`] directly.
+pub async fn trim_target(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    args: &TrimArgs,
+) -> Result<TrimTarget, CliError> {
+    use std::time::SystemTime;
+
+    let tail = check_tail(s2, uri.clone()).await?.seq_num;
+    let trim_point = match (args.before_timestamp, args.older_than, args.keep_last) {
+        (Some(ts), None, None) => {
+            first_seq_num_from(s2, uri.clone(), ReadFrom::Timestamp(ts), tail).await?
+        }
+        (None, Some(age), None) => {
+            let ts = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+                .saturating_sub(age.as_millis()) as u64;
+            first_seq_num_from(s2, uri.clone(), ReadFrom::Timestamp(ts), tail).await?
+        }
+        (None, None, Some(keep)) => tail.saturating_sub(keep),
+        _ => unreachable!("clap ensures exactly one relative trim option"),
+    };
+
+    let first = first_seq_num_from(s2, uri, ReadFrom::SeqNum(0), tail).await?;
+    Ok(TrimTarget {
+        trim_point,
+        records: trim_point.min(tail).saturating_sub(first),
+    })
+}
+
+pub async fn trim(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    trim_point: u64,
+    args: &TrimArgs,
+) -> Result<AppendAck, CliError> {
+    let stream = s2.basin(uri.basin).stream(uri.stream);
     append_command(
         &stream,
-        CommandRecord::trim(args.trim_point),
-        args.fencing_token,
+        CommandRecord::trim(trim_point),
+        args.fencing_token.clone(),
         args.match_seq_num,
         OpKind::Trim,
     )
@@ -412,27 +483,39 @@ pub async fn fence(s2: &S2, args: FenceArgs) -> Result<AppendAck, CliError> {
     .await
 }
 
-pub async fn read(s2: &S2, args: &ReadArgs) -> Result<Streaming<ReadBatch>, CliError> {
+/// Start position from mutually exclusive start options, if any is specified.
+pub fn read_from(
+    seq_num: Option<u64>,
+    timestamp: Option<u64>,
+    tail_offset: Option<u64>,
+    ago: Option<humantime::Duration>,
+) -> Option<ReadFrom> {
     use std::time::SystemTime;
 
-    let uri = args.uri.clone();
-    let stream = s2.basin(uri.basin).stream(uri.stream);
-
-    let from = match (args.seq_num, args.timestamp, args.tail_offset, args.ago) {
-        (Some(seq), None, None, None) => ReadFrom::SeqNum(seq),
-        (None, Some(ts), None, None) => ReadFrom::Timestamp(ts),
-        (None, None, Some(offset), None) => ReadFrom::TailOffset(offset),
+    match (seq_num, timestamp, tail_offset, ago) {
+        (Some(seq), None, None, None) => Some(ReadFrom::SeqNum(seq)),
+        (None, Some(ts), None, None) => Some(ReadFrom::Timestamp(ts)),
+        (None, None, Some(offset), None) => Some(ReadFrom::TailOffset(offset)),
         (None, None, None, Some(ago)) => {
             let ts = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
                 .as_millis()
                 .saturating_sub(ago.as_millis()) as u64;
-            ReadFrom::Timestamp(ts)
+            Some(ReadFrom::Timestamp(ts))
         }
-        (None, None, None, None) => ReadFrom::TailOffset(0),
+        (None, None, None, None) => None,
         _ => unreachable!("clap ensures only one start option"),
-    };
+    }
+}
+
+pub async fn read(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    args: &ReadArgs,
+) -> Result<Streaming<ReadBatch>, CliError> {
+    let from = read_from(args.seq_num, args.timestamp, args.tail_offset, args.ago)
+        .unwrap_or(ReadFrom::TailOffset(0));
 
     let start = ReadStart::new()
         .with_from(from)
@@ -451,12 +534,39 @@ pub async fn read(s2: &S2, args: &ReadArgs) -> Result<Streaming<ReadBatch>, CliE
         stop = stop.with_until(..until);
     }
 
+    read_session(
+        s2,
+        uri,
+        ReadInput::new()
+            .with_start(start)
+            .with_stop(stop)
+            .with_ignore_command_records(args.commands.skip_commands),
+    )
+    .await
+}
+
+pub async fn read_session(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    input: ReadInput,
+) -> Result<Streaming<ReadBatch>, CliError> {
+    let stream = s2.basin(uri.basin).stream(uri.stream);
     stream
-        .read_session(ReadInput::new().with_start(start).with_stop(stop))
+        .read_session(input)
         .await
         .map_err(|e| CliError::op(OpKind::Read, e))
 }
 
+/// Flatten read batches into individual records.
+pub fn records(
+    batches: Streaming<ReadBatch>,
+) -> impl Stream<Item = Result<SequencedRecord, CliError>> + Send {
+    batches
+        .map_err(|e| CliError::op(OpKind::Read, e))
+        .map_ok(|batch| stream::iter(batch.records.into_iter().map(Ok)))
+        .try_flatten()
+}
+
 pub fn append<'a, S, E>(
     s2: &'a S2,
     records: S,
@@ -557,9 +667,9 @@ where
 
 pub async fn tail(
     s2: &S2,
+    uri: S2BasinAndStreamUri,
     args: &TailArgs,
 ) -> Result<Pin<Box<dyn Stream<Item = Result<SequencedRecord, CliError>> + Send>>, CliError> {
-    let uri = args.uri.clone();
     let stream = s2.basin(uri.basin).stream(uri.stream);
 
     let start = ReadStart::new().with_from(ReadFrom::TailOffset(args.lines));
@@ -570,7 +680,12 @@ pub async fn tail(
     };
 
     let batches = stream
-        .read_session(ReadInput::new().with_start(start).with_stop(stop))
+        .read_session(
+            ReadInput::new()
+                .with_start(start)
+                .with_stop(stop)
+                .with_ignore_command_records(args.commands.skip_commands),
+        )
         .await
         .map_err(|e| CliError::op(OpKind::Tail, e))?;
 
@@ -591,9 +706,44 @@ async fn append_command(
     match_seq_num: Option<u64>,
     op_error: OpKind,
 ) -> Result<AppendAck, CliError> {
-    let record: AppendRecord = command.into();
+    append_one(
+        stream,
+        command.into(),
+        fencing_token,
+        match_seq_num,
+        op_error,
+    )
+    .await
+}
+
+/// Append a single record with a unary append, rather than an append session.
+pub async fn append_record(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    record: AppendRecord,
+    fencing_token: Option<FencingToken>,
+    match_seq_num: Option<u64>,
+) -> Result<AppendAck, CliError> {
+    let stream = s2.basin(uri.basin).stream(uri.stream);
+    append_one(
+        &stream,
+        record,
+        fencing_token,
+        match_seq_num,
+        OpKind::Append,
+    )
+    .await
+}
+
+async fn append_one(
+    stream: &S2Stream,
+    record: AppendRecord,
+    fencing_token: Option<FencingToken>,
+    match_seq_num: Option<u64>,
+    op_error: OpKind,
+) -> Result<AppendAck, CliError> {
     let records = AppendRecordBatch::try_from_iter([record])
-        .expect("single command record should always fit in a batch");
+        .expect("single record should always fit in a batch");
     let mut input = AppendInput::new(records);
     if let Some(ft) = fencing_token {
         input = input.with_fencing_token(ft);
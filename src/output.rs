@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use futures::{Stream, TryStreamExt};
+use json_to_table::json_to_table;
+use serde::Serialize;
+
+use crate::error::CliError;
+
+/// How command results are rendered on stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-friendly tables and colored text.
+    #[default]
+    Table,
+    /// Pretty-printed JSON. Lists are rendered as a single array.
+    Json,
+    /// Newline-delimited JSON, one compact object per line.
+    Ndjson,
+    /// YAML. Lists are rendered as a single sequence.
+    Yaml,
+    /// Plain text without colors or table borders.
+    Plain,
+}
+
+impl OutputFormat {
+    /// Whether the output is meant to be parsed rather than read.
+    pub fn is_structured(self) -> bool {
+        matches!(self, Self::Json | Self::Ndjson | Self::Yaml)
+    }
+}
+
+/// Render a single value.
+pub fn print_value<T: Serialize>(format: OutputFormat, value: &T) -> Result<(), CliError> {
+    let value = serde_json::to_value(value)?;
+    match format {
+        OutputFormat::Table => println!("{}", json_to_table(&value)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(&value)?),
+        OutputFormat::Yaml => print!("{}", to_yaml(&value)?),
+        OutputFormat::Plain => print!("{}", to_plain(&value)),
+    }
+    Ok(())
+}
+
+/// Render a stream of items, using `line` for the human-friendly formats.
+///
+/// NDJSON output is written as items arrive, while JSON and YAML collect the
+/// items into a single document.
+pub async fn print_list<T, S, F>(format: OutputFormat, items: S, line: F) -> Result<(), CliError>
+where
+    T: Serialize,
+    S: Stream<Item = Result<T, CliError>>,
+    F: Fn(&T) -> String,
+{
+    let mut items = std::pin::pin!(items);
+    match format {
+        OutputFormat::Table | OutputFormat::Plain => {
+            while let Some(item) = items.try_next().await? {
+                println!("{}", line(&item));
+            }
+        }
+        OutputFormat::Ndjson => {
+            let mut stdout = std::io::stdout().lock();
+            while let Some(item) = items.try_next().await? {
+                serde_json::to_writer(&mut stdout, &item)?;
+                writeln!(stdout).map_err(|e| CliError::RecordWrite(e.to_string()))?;
+            }
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let mut values = Vec::new();
+            while let Some(item) = items.try_next().await? {
+                values.push(serde_json::to_value(&item)?);
+            }
+            print_value(format, &values)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_yaml(value: &serde_json::Value) -> Result<String, CliError> {
+    serde_yaml_ng::to_string(value).map_err(|e| CliError::RecordWrite(e.to_string()))
+}
+
+/// Flatten a value into `key.path=value` lines.
+pub fn to_plain(value: &serde_json::Value) -> String {
+    fn flatten(prefix: &str, value: &serde_json::Value, out: &mut String) {
+        let key = |k: &str| {
+            if prefix.is_empty() {
+                k.to_owned()
+            } else {
+                format!("{prefix}.{k}")
+            }
+        };
+        match value {
+            serde_json::Value::Object(map) => {
+                for (k, v) in map {
+                    flatten(&key(k), v, out);
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    flatten(&key(&i.to_string()), v, out);
+                }
+            }
+            serde_json::Value::Null => {}
+            serde_json::Value::String(s) if prefix.is_empty() => out.push_str(&format!("{s}\n")),
+            serde_json::Value::String(s) => out.push_str(&format!("{prefix}={s}\n")),
+            other if prefix.is_empty() => out.push_str(&format!("{other}\n")),
+            other => out.push_str(&format!("{prefix}={other}\n")),
+        }
+    }
+
+    let mut out = String::new();
+    flatten("", value, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::to_plain;
+
+    #[test]
+    fn test_to_plain() {
+        let value = json!({
+            "name": "basin",
+            "config": { "retention": 3600, "storage_class": null },
+            "ops": ["read", "append"],
+        });
+        assert_eq!(
+            to_plain(&value),
+            "name=basin\nconfig.retention=3600\nops.0=read\nops.1=append\n"
+        );
+        assert_eq!(to_plain(&json!("token")), "token\n");
+    }
+}
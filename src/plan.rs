@@ -0,0 +1,801 @@
+//! Declarative manifests of basins and streams, diffed against live state by
+//! `s2 plan` and reconciled by `s2 apply`.
+//!
+//! A manifest is a TOML file keyed by basin and stream name:
+//!
+//! ```toml
+//! [basins.my-basin]
+//! create_stream_on_append = true
+//! default_stream_config = { storage_class = "express" }
+//!
+//! [basins.my-basin.streams.events]
+//! retention_policy = "7d"
+//! timestamping = { timestamping_mode = "arrival" }
+//! ```
+//!
+//! Basin and stream config fields left out of the manifest are not managed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use colored::Colorize;
+use futures::TryStreamExt;
+use s2_sdk::{
+    S2,
+    types::{BasinName, BasinState, StreamName},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{
+        CreateBasinArgs, CreateStreamArgs, ListBasinsArgs, ListStreamsArgs, ReconfigureBasinArgs,
+    },
+    error::CliError,
+    ops,
+    types::{
+        BasinConfig, RetentionPolicy, S2BasinAndMaybeStreamUri, S2BasinAndStreamUri, S2BasinUri,
+        StreamConfig, TimestampingConfig, serialize_display,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub basins: Vec<BasinSpec>,
+}
+
+#[derive(Debug)]
+pub struct BasinSpec {
+    pub name: BasinName,
+    pub config: BasinSpecConfig,
+    pub streams: Vec<(StreamName, StreamConfig)>,
+}
+
+/// Basin config as written in a manifest, where unset fields are not managed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BasinSpecConfig {
+    #[serde(default)]
+    pub default_stream_config: StreamConfig,
+    pub create_stream_on_append: Option<bool>,
+    pub create_stream_on_read: Option<bool>,
+}
+
+impl From<BasinConfig> for BasinSpecConfig {
+    fn from(config: BasinConfig) -> Self {
+        Self {
+            default_stream_config: config.default_stream_config,
+            create_stream_on_append: Some(config.create_stream_on_append),
+            create_stream_on_read: Some(config.create_stream_on_read),
+        }
+    }
+}
+
+impl From<BasinSpecConfig> for BasinConfig {
+    fn from(config: BasinSpecConfig) -> Self {
+        Self {
+            default_stream_config: config.default_stream_config,
+            create_stream_on_append: config.create_stream_on_append.unwrap_or_default(),
+            create_stream_on_read: config.create_stream_on_read.unwrap_or_default(),
+        }
+    }
+}
+
+/// Serialized form of a [`Manifest`], keyed and ordered by name.
+#[derive(Debug, Serialize, Deserialize)]
+struct RawManifest {
+    #[serde(default)]
+    basins: BTreeMap<String, RawBasinSpec>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawBasinSpec {
+    #[serde(flatten)]
+    config: BasinSpecConfig,
+    #[serde(default)]
+    streams: BTreeMap<String, StreamConfig>,
+}
+
+impl FromStr for Manifest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw: RawManifest = toml::from_str(s).map_err(|e| e.to_string())?;
+        let basins = raw
+            .basins
+            .into_iter()
+            .map(|(name, spec)| {
+                let name: BasinName = name
+                    .parse()
+                    .map_err(|e| format!("invalid basin name `{name}`: {e}"))?;
+                let streams = spec
+                    .streams
+                    .into_iter()
+                    .map(|(stream, config)| {
+                        let stream = stream
+                            .parse()
+                            .map_err(|e| format!("invalid stream name `{stream}`: {e}"))?;
+                        Ok((stream, config))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(BasinSpec {
+                    name,
+                    config: spec.config,
+                    streams,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { basins })
+    }
+}
+
+/// File format of a manifest written by `dump-config`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    /// TOML, as read by `plan` and `apply`.
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let error = |msg: String| CliError::Manifest(path.to_owned(), msg);
+        let contents = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        contents.parse().map_err(error)
+    }
+
+    /// Snapshot the config of `basin` and its streams.
+    pub async fn dump(s2: &S2, basin: &BasinName) -> Result<Self, CliError> {
+        let config = BasinConfig::from(ops::get_basin_config(s2, basin).await?).into();
+        let mut streams = Vec::new();
+        for stream in live_streams(s2, basin).await? {
+            let stream: StreamName = stream.parse().expect("listed stream name is valid");
+            let uri = S2BasinAndStreamUri {
+                basin: basin.clone(),
+                stream: stream.clone(),
+            };
+            let config = ops::get_stream_config(s2, uri).await?.into();
+            streams.push((stream, config));
+        }
+        Ok(Self {
+            basins: vec![BasinSpec {
+                name: basin.clone(),
+                config,
+                streams,
+            }],
+        })
+    }
+
+    pub fn render(&self, format: ManifestFormat) -> Result<String, CliError> {
+        let raw = RawManifest {
+            basins: self
+                .basins
+                .iter()
+                .map(|spec| {
+                    let streams = spec
+                        .streams
+                        .iter()
+                        .map(|(stream, config)| (stream.to_string(), config.clone()))
+                        .collect();
+                    let raw = RawBasinSpec {
+                        config: spec.config.clone(),
+                        streams,
+                    };
+                    (spec.name.to_string(), raw)
+                })
+                .collect(),
+        };
+        let error = |e: &dyn std::fmt::Display| CliError::RecordWrite(e.to_string());
+        match format {
+            ManifestFormat::Toml => toml::to_string(&raw).map_err(|e| error(&e)),
+            ManifestFormat::Yaml => serde_yaml_ng::to_string(&raw).map_err(|e| error(&e)),
+            ManifestFormat::Json => serde_json::to_string_pretty(&raw)
+                .map(|json| json + "\n")
+                .map_err(|e| error(&e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// A change needed to make live state match a manifest.
+#[derive(Debug, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    CreateBasin {
+        #[serde(serialize_with = "serialize_display")]
+        basin: BasinName,
+        config: BasinConfig,
+    },
+    ReconfigureBasin {
+        #[serde(serialize_with = "serialize_display")]
+        basin: BasinName,
+        changes: Vec<FieldChange>,
+        #[serde(skip)]
+        create_stream_on_append: Option<bool>,
+        #[serde(skip)]
+        create_stream_on_read: Option<bool>,
+        #[serde(skip)]
+        default_stream_config: StreamConfig,
+    },
+    CreateStream {
+        #[serde(serialize_with = "serialize_display")]
+        uri: S2BasinAndStreamUri,
+        config: StreamConfig,
+    },
+    ReconfigureStream {
+        #[serde(serialize_with = "serialize_display")]
+        uri: S2BasinAndStreamUri,
+        changes: Vec<FieldChange>,
+        #[serde(skip)]
+        config: StreamConfig,
+    },
+    DeleteStream {
+        #[serde(serialize_with = "serialize_display")]
+        uri: S2BasinAndStreamUri,
+    },
+}
+
+impl Action {
+    /// Human-readable description, with one indented line per changed field.
+    pub fn describe(&self) -> String {
+        let (line, changes) = match self {
+            Action::CreateBasin { basin, .. } => (format!("+ create basin {basin}").green(), None),
+            Action::ReconfigureBasin { basin, changes, .. } => (
+                format!("~ reconfigure basin {basin}").yellow(),
+                Some(changes),
+            ),
+            Action::CreateStream { uri, .. } => (format!("+ create stream {uri}").green(), None),
+            Action::ReconfigureStream { uri, changes, .. } => (
+                format!("~ reconfigure stream {uri}").yellow(),
+                Some(changes),
+            ),
+            Action::DeleteStream { uri } => (format!("- delete stream {uri}").red(), None),
+        };
+        let mut out = line.bold().to_string();
+        for change in changes.into_iter().flatten() {
+            out.push_str(&format!(
+                "\n    {}: {} -> {}",
+                change.field,
+                change.from.as_deref().unwrap_or("<unset>"),
+                change.to
+            ));
+        }
+        out
+    }
+}
+
+fn label(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+fn retention_label(policy: &RetentionPolicy) -> String {
+    match policy {
+        RetentionPolicy::Age(age) => humantime::format_duration(*age).to_string(),
+        RetentionPolicy::Infinite => "infinite".to_owned(),
+    }
+}
+
+/// Compare a value that is only managed when `desired` is set.
+fn compare<T: PartialEq>(
+    changes: &mut Vec<FieldChange>,
+    field: String,
+    desired: Option<&T>,
+    live: Option<&T>,
+    label: impl Fn(&T) -> String,
+) -> bool {
+    match desired {
+        Some(desired) if live != Some(desired) => {
+            changes.push(FieldChange {
+                field,
+                from: live.map(&label),
+                to: label(desired),
+            });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Changes to turn `live` into `desired`, and a config holding only the changed fields.
+fn diff_stream_config(
+    prefix: &str,
+    desired: &StreamConfig,
+    live: &StreamConfig,
+) -> (Vec<FieldChange>, StreamConfig) {
+    let mut changes = Vec::new();
+    let mut update = StreamConfig::default();
+
+    if compare(
+        &mut changes,
+        format!("{prefix}storage_class"),
+        desired.storage_class.as_ref(),
+        live.storage_class.as_ref(),
+        label,
+    ) {
+        update.storage_class = desired.storage_class.clone();
+    }
+    if compare(
+        &mut changes,
+        format!("{prefix}retention_policy"),
+        desired.retention_policy.as_ref(),
+        live.retention_policy.as_ref(),
+        retention_label,
+    ) {
+        update.retention_policy = desired.retention_policy.clone();
+    }
+
+    if let Some(timestamping) = &desired.timestamping {
+        let live_timestamping = live.timestamping.as_ref();
+        let mut timestamping_update = TimestampingConfig {
+            timestamping_mode: None,
+            timestamping_uncapped: None,
+        };
+        if compare(
+            &mut changes,
+            format!("{prefix}timestamping.timestamping_mode"),
+            timestamping.timestamping_mode.as_ref(),
+            live_timestamping.and_then(|t| t.timestamping_mode.as_ref()),
+            label,
+        ) {
+            timestamping_update.timestamping_mode = timestamping.timestamping_mode.clone();
+        }
+        if compare(
+            &mut changes,
+            format!("{prefix}timestamping.timestamping_uncapped"),
+            timestamping.timestamping_uncapped.as_ref(),
+            live_timestamping.and_then(|t| t.timestamping_uncapped.as_ref()),
+            bool::to_string,
+        ) {
+            timestamping_update.timestamping_uncapped = timestamping.timestamping_uncapped;
+        }
+        if timestamping_update.timestamping_mode.is_some()
+            || timestamping_update.timestamping_uncapped.is_some()
+        {
+            update.timestamping = Some(timestamping_update);
+        }
+    }
+
+    // A stream without delete-on-empty behaves as if its minimum age were zero.
+    let live_min_age = live
+        .delete_on_empty
+        .as_ref()
+        .map_or(Duration::ZERO, |d| d.delete_on_empty_min_age);
+    if compare(
+        &mut changes,
+        format!("{prefix}delete_on_empty.delete_on_empty_min_age"),
+        desired
+            .delete_on_empty
+            .as_ref()
+            .map(|d| &d.delete_on_empty_min_age),
+        Some(&live_min_age),
+        |age| humantime::format_duration(*age).to_string(),
+    ) {
+        update.delete_on_empty = desired.delete_on_empty.clone();
+    }
+
+    (changes, update)
+}
+
+fn diff_basin_config(
+    basin: &BasinName,
+    desired: &BasinSpecConfig,
+    live: &BasinConfig,
+) -> Option<Action> {
+    let (mut changes, default_stream_config) = diff_stream_config(
+        "default_stream_config.",
+        &desired.default_stream_config,
+        &live.default_stream_config,
+    );
+    let create_stream_on_append = compare(
+        &mut changes,
+        "create_stream_on_append".to_owned(),
+        desired.create_stream_on_append.as_ref(),
+        Some(&live.create_stream_on_append),
+        bool::to_string,
+    )
+    .then_some(desired.create_stream_on_append)
+    .flatten();
+    let create_stream_on_read = compare(
+        &mut changes,
+        "create_stream_on_read".to_owned(),
+        desired.create_stream_on_read.as_ref(),
+        Some(&live.create_stream_on_read),
+        bool::to_string,
+    )
+    .then_some(desired.create_stream_on_read)
+    .flatten();
+
+    (!changes.is_empty()).then(|| Action::ReconfigureBasin {
+        basin: basin.clone(),
+        changes,
+        create_stream_on_append,
+        create_stream_on_read,
+        default_stream_config,
+    })
+}
+
+/// State of `basin`, or `None` if it does not exist.
+async fn basin_state(s2: &S2, basin: &BasinName) -> Result<Option<BasinState>, CliError> {
+    let prefix = basin
+        .to_string()
+        .parse()
+        .expect("basin name is a valid prefix");
+    let basins = ops::list_basins(
+        s2,
+        ListBasinsArgs {
+            prefix: Some(prefix),
+            start_after: None,
+            limit: None,
+            no_auto_paginate: false,
+        },
+    )
+    .await?;
+    let found = basins
+        .try_filter(|info| futures::future::ready(&info.name == basin))
+        .try_next()
+        .await?;
+    Ok(found.map(|info| info.state))
+}
+
+async fn basin_exists(s2: &S2, basin: &BasinName) -> Result<bool, CliError> {
+    let state = basin_state(s2, basin).await?;
+    Ok(state.is_some_and(|state| !matches!(state, BasinState::Deleting)))
+}
+
+/// Poll until a newly created basin is active, so that its streams can be created.
+async fn wait_for_basin(s2: &S2, basin: &BasinName) -> Result<(), CliError> {
+    for _ in 0..BASIN_ACTIVE_POLLS {
+        if matches!(basin_state(s2, basin).await?, Some(BasinState::Active)) {
+            return Ok(());
+        }
+        tokio::time::sleep(BASIN_ACTIVE_POLL_INTERVAL).await;
+    }
+    Err(CliError::BasinNotActive(basin.to_string()))
+}
+
+async fn live_streams(s2: &S2, basin: &BasinName) -> Result<BTreeSet<String>, CliError> {
+    let streams = ops::list_streams(
+        s2,
+        ListStreamsArgs {
+            uri: S2BasinAndMaybeStreamUri {
+                basin: basin.clone(),
+                stream: None,
+            },
+            prefix: None,
+            start_after: None,
+            limit: None,
+            no_auto_paginate: false,
+        },
+    )
+    .await?;
+    streams
+        .try_filter(|info| futures::future::ready(info.deleted_at.is_none()))
+        .map_ok(|info| info.name.to_string())
+        .try_collect()
+        .await
+}
+
+const BASIN_ACTIVE_POLLS: usize = 120;
+const BASIN_ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Compute the actions needed to make live state match `manifest`.
+///
+/// With `prune`, streams in managed basins that are not in the manifest are deleted.
+pub async fn plan(s2: &S2, manifest: &Manifest, prune: bool) -> Result<Vec<Action>, CliError> {
+    let mut actions = Vec::new();
+    for spec in &manifest.basins {
+        let basin = &spec.name;
+        let uri = |stream: &StreamName| S2BasinAndStreamUri {
+            basin: basin.clone(),
+            stream: stream.clone(),
+        };
+
+        if !basin_exists(s2, basin).await? {
+            actions.push(Action::CreateBasin {
+                basin: basin.clone(),
+                config: spec.config.clone().into(),
+            });
+            actions.extend(
+                spec.streams
+                    .iter()
+                    .map(|(stream, config)| Action::CreateStream {
+                        uri: uri(stream),
+                        config: config.clone(),
+                    }),
+            );
+            continue;
+        }
+
+        let live_config = ops::get_basin_config(s2, basin).await?.into();
+        actions.extend(diff_basin_config(basin, &spec.config, &live_config));
+
+        let mut live = live_streams(s2, basin).await?;
+        for (stream, desired) in &spec.streams {
+            if !live.remove(stream.as_ref()) {
+                actions.push(Action::CreateStream {
+                    uri: uri(stream),
+                    config: desired.clone(),
+                });
+                continue;
+            }
+            let live_config = ops::get_stream_config(s2, uri(stream)).await?.into();
+            let (changes, config) = diff_stream_config("", desired, &live_config);
+            if !changes.is_empty() {
+                actions.push(Action::ReconfigureStream {
+                    uri: uri(stream),
+                    changes,
+                    config,
+                });
+            }
+        }
+
+        // Streams left in `live` are not in the manifest.
+        if prune {
+            for stream in live {
+                let stream = stream.parse().expect("listed stream name is valid");
+                actions.push(Action::DeleteStream { uri: uri(&stream) });
+            }
+        }
+    }
+    Ok(actions)
+}
+
+/// Perform an action computed by [`plan`].
+pub async fn apply(s2: &S2, action: &Action) -> Result<(), CliError> {
+    match action {
+        Action::CreateBasin { basin, config } => {
+            let info = ops::create_basin(
+                s2,
+                CreateBasinArgs {
+                    basin: S2BasinUri(basin.clone()),
+                    config: config.clone(),
+                },
+            )
+            .await?;
+            if !matches!(info.state, BasinState::Active) {
+                wait_for_basin(s2, basin).await?;
+            }
+        }
+        Action::ReconfigureBasin {
+            basin,
+            create_stream_on_append,
+            create_stream_on_read,
+            default_stream_config,
+            ..
+        } => {
+            ops::reconfigure_basin(
+                s2,
+                ReconfigureBasinArgs {
+                    basin: S2BasinUri(basin.clone()),
+                    create_stream_on_append: *create_stream_on_append,
+                    create_stream_on_read: *create_stream_on_read,
+                    default_stream_config: default_stream_config.clone(),
+                },
+            )
+            .await?;
+        }
+        Action::CreateStream { uri, config } => {
+            ops::create_stream(
+                s2,
+                CreateStreamArgs {
+                    uri: uri.clone(),
+                    config: config.clone(),
+                },
+            )
+            .await?;
+        }
+        Action::ReconfigureStream { uri, config, .. } => {
+            ops::reconfigure_stream(s2, uri.clone(), config.clone()).await?;
+        }
+        Action::DeleteStream { uri } => {
+            ops::delete_stream(s2, uri.clone()).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        BasinSpec, BasinSpecConfig, Manifest, ManifestFormat, diff_basin_config, diff_stream_config,
+    };
+    use crate::types::{
+        BasinConfig, RetentionPolicy, StorageClass, StreamConfig, TimestampingConfig,
+        TimestampingMode,
+    };
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: Manifest = r#"
+            [basins.my-basin-1]
+            create_stream_on_append = true
+            default_stream_config = { storage_class = "express" }
+
+            [basins.my-basin-1.streams.events]
+            retention_policy = "7d"
+            timestamping = { timestamping_mode = "arrival" }
+
+            [basins.my-basin-1.streams.audit]
+            retention_policy = "infinite"
+            delete_on_empty = { delete_on_empty_min_age = "1h" }
+            "#
+        .parse()
+        .unwrap();
+
+        let [spec] = &manifest.basins[..] else {
+            panic!("expected one basin");
+        };
+        assert_eq!(spec.name.as_ref(), "my-basin-1");
+        assert_eq!(spec.config.create_stream_on_append, Some(true));
+        assert_eq!(spec.config.create_stream_on_read, None);
+        assert_eq!(
+            spec.config.default_stream_config.storage_class,
+            Some(StorageClass::Express)
+        );
+
+        // Streams are ordered by name.
+        let [(audit_name, audit), (events_name, events)] = &spec.streams[..] else {
+            panic!("expected two streams");
+        };
+        assert_eq!(
+            (audit_name.as_ref(), events_name.as_ref()),
+            ("audit", "events")
+        );
+        assert_eq!(
+            events.retention_policy,
+            Some(RetentionPolicy::Age(Duration::from_secs(7 * 24 * 3600)))
+        );
+        assert_eq!(
+            events.timestamping.as_ref().unwrap().timestamping_mode,
+            Some(TimestampingMode::Arrival)
+        );
+        assert_eq!(audit.retention_policy, Some(RetentionPolicy::Infinite));
+        assert_eq!(
+            audit
+                .delete_on_empty
+                .as_ref()
+                .unwrap()
+                .delete_on_empty_min_age,
+            Duration::from_secs(3600)
+        );
+    }
+
+    #[test]
+    fn test_render_manifest() {
+        let config = StreamConfig {
+            retention_policy: Some(RetentionPolicy::Age(Duration::from_secs(86400))),
+            ..StreamConfig::default()
+        };
+        let manifest = Manifest {
+            basins: vec![BasinSpec {
+                name: "my-basin-1".parse().unwrap(),
+                config: BasinSpecConfig {
+                    default_stream_config: StreamConfig::default(),
+                    create_stream_on_append: Some(true),
+                    create_stream_on_read: Some(false),
+                },
+                streams: vec![
+                    ("a".parse().unwrap(), config.clone()),
+                    ("b".parse().unwrap(), StreamConfig::default()),
+                ],
+            }],
+        };
+
+        let parsed: Manifest = manifest
+            .render(ManifestFormat::Toml)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let [spec] = &parsed.basins[..] else {
+            panic!("expected one basin");
+        };
+        assert_eq!(spec.config.create_stream_on_append, Some(true));
+        assert_eq!(spec.config.create_stream_on_read, Some(false));
+        assert_eq!(spec.streams.len(), 2);
+        assert_eq!(spec.streams[0].1, config);
+        assert_eq!(spec.streams[1].1, StreamConfig::default());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&manifest.render(ManifestFormat::Json).unwrap()).unwrap();
+        let streams = json["basins"]["my-basin-1"]["streams"].as_object().unwrap();
+        assert_eq!(streams.keys().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        assert!("[basins.-invalid]".parse::<Manifest>().is_err());
+        assert!(
+            "[basins.my-basin-1.streams.\"\"]"
+                .parse::<Manifest>()
+                .is_err()
+        );
+        assert!("basins = 1".parse::<Manifest>().is_err());
+    }
+
+    #[test]
+    fn test_diff_stream_config() {
+        let live = StreamConfig {
+            storage_class: Some(StorageClass::Standard),
+            retention_policy: Some(RetentionPolicy::Age(Duration::from_secs(86400))),
+            timestamping: Some(TimestampingConfig {
+                timestamping_mode: Some(TimestampingMode::ClientPrefer),
+                timestamping_uncapped: Some(false),
+            }),
+            delete_on_empty: None,
+        };
+
+        let (changes, update) = diff_stream_config("", &StreamConfig::default(), &live);
+        assert!(changes.is_empty());
+        assert_eq!(update, StreamConfig::default());
+
+        let desired = StreamConfig {
+            storage_class: Some(StorageClass::Standard),
+            retention_policy: Some(RetentionPolicy::Infinite),
+            timestamping: Some(TimestampingConfig {
+                timestamping_mode: Some(TimestampingMode::ClientPrefer),
+                timestamping_uncapped: Some(true),
+            }),
+            delete_on_empty: None,
+        };
+        let (changes, update) = diff_stream_config("", &desired, &live);
+        let fields: Vec<_> = changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            ["retention_policy", "timestamping.timestamping_uncapped"]
+        );
+        assert_eq!(changes[0].from.as_deref(), Some("1day"));
+        assert_eq!(changes[0].to, "infinite");
+        assert_eq!(
+            update,
+            StreamConfig {
+                storage_class: None,
+                retention_policy: Some(RetentionPolicy::Infinite),
+                timestamping: Some(TimestampingConfig {
+                    timestamping_mode: None,
+                    timestamping_uncapped: Some(true),
+                }),
+                delete_on_empty: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_basin_config() {
+        let basin = "my-basin-1".parse().unwrap();
+        let live = BasinConfig {
+            default_stream_config: StreamConfig::default(),
+            create_stream_on_append: true,
+            create_stream_on_read: false,
+        };
+        assert!(diff_basin_config(&basin, &live.clone().into(), &live).is_none());
+
+        // Omitted fields are not managed.
+        assert!(diff_basin_config(&basin, &BasinSpecConfig::default(), &live).is_none());
+
+        let desired = BasinSpecConfig {
+            create_stream_on_read: Some(true),
+            ..BasinSpecConfig::default()
+        };
+        let action = diff_basin_config(&basin, &desired, &live).unwrap();
+        let description = action.describe();
+        assert!(description.contains("create_stream_on_read: false -> true"));
+        assert!(!description.contains("create_stream_on_append"));
+    }
+}
@@ -2,12 +2,20 @@ use std::io;
 use std::io::BufRead;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
 
+use bytes::{Bytes, BytesMut};
 use clap::ValueEnum;
-use futures::Stream;
-use s2_sdk::types::{AppendRecord, SequencedRecord};
+use futures::{Stream, StreamExt, TryStreamExt};
+use regex::Regex;
+use s2_sdk::types::{AppendRecord, Header, SequencedRecord};
 use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncBufReadExt, AsyncWrite, BufWriter};
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, BufWriter, ReadBuf,
+};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::{LinesStream, ReceiverStream};
 use tracing::trace;
@@ -29,6 +37,16 @@ pub enum RecordFormat {
     /// JSON format with headers and body encoded as Base64.
     #[clap(aliases = ["base64", "json-binsafe"])]
     JsonBase64,
+    /// CSV with a header row naming the columns.
+    /// Columns may be `seq_num`, `timestamp`, `body`, or a header name.
+    /// When appending, the header row determines how columns are mapped,
+    /// and the `seq_num` column is ignored.
+    Csv,
+    /// Compact binary framing, lossless for any headers and body.
+    /// Each record is a sequence of unsigned LEB128 varints and length-prefixed bytes:
+    /// `seq_num timestamp header_count (name_len name value_len value)* body_len body`.
+    /// When appending, the `seq_num` is ignored.
+    Binary,
 }
 
 #[derive(Debug, Clone)]
@@ -44,10 +62,131 @@ pub enum RecordsOut {
     Stdout,
 }
 
+/// How input is split into records, and how records are separated on output.
+#[derive(Debug, Clone)]
+pub enum RecordDelimiter {
+    /// Records are separated by a byte sequence.
+    Bytes(Vec<u8>),
+    /// Records start at lines matching a pattern, so a record may span lines.
+    /// Records are separated by newlines on output.
+    RecordStart(Regex),
+}
+
+impl Default for RecordDelimiter {
+    fn default() -> Self {
+        Self::Bytes(b"\n".to_vec())
+    }
+}
+
+impl RecordDelimiter {
+    /// Bytes written between records.
+    pub fn separator(&self) -> &[u8] {
+        match self {
+            Self::Bytes(bytes) => bytes,
+            Self::RecordStart(_) => b"\n",
+        }
+    }
+}
+
+impl FromStr for RecordDelimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('^') {
+            return Regex::new(s)
+                .map(Self::RecordStart)
+                .map_err(|e| format!("invalid record start pattern: {e}"));
+        }
+
+        let bytes = unescape(s)?;
+        if bytes.is_empty() {
+            return Err("delimiter cannot be empty".to_owned());
+        }
+        Ok(Self::Bytes(bytes))
+    }
+}
+
+/// Expand the escapes `\0`, `\n`, `\r`, `\t`, `\\` and `\xNN` into bytes.
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('0') => bytes.push(b'\0'),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("invalid escape: \\x{hex}"))?;
+                bytes.push(byte);
+            }
+            Some(c) => return Err(format!("invalid escape: \\{c}")),
+            None => return Err("trailing backslash".to_owned()),
+        }
+    }
+    Ok(bytes)
+}
+
 impl RecordsIn {
+    /// Records as strings, split according to the delimiter.
     pub async fn reader(
         &self,
+        delimiter: &RecordDelimiter,
     ) -> io::Result<Pin<Box<dyn Stream<Item = io::Result<String>> + Send>>> {
+        match delimiter {
+            RecordDelimiter::Bytes(bytes) if bytes == b"\n" => self.lines().await,
+            RecordDelimiter::Bytes(bytes) => Ok(Box::pin(split_stream(
+                self.byte_reader().await?,
+                bytes.clone(),
+            ))),
+            RecordDelimiter::RecordStart(pattern) => Ok(Box::pin(
+                group_lines_stream(self.lines().await?.map_ok(|l| (l, ())), pattern.clone())
+                    .map_ok(|(s, ())| s),
+            )),
+        }
+    }
+
+    /// Like [`Self::reader`], but starting at a byte offset into a file.
+    /// The returned offset tracks the end of the last record yielded.
+    pub async fn reader_at(
+        &self,
+        delimiter: &RecordDelimiter,
+        start: u64,
+    ) -> io::Result<(
+        Pin<Box<dyn Stream<Item = io::Result<String>> + Send>>,
+        InputOffset,
+    )> {
+        let file = self.file_at(start).await?;
+        let offset = InputOffset::new(start);
+        let lines = match delimiter {
+            RecordDelimiter::Bytes(bytes) => {
+                split_offsets_stream(file, bytes.clone(), start).left_stream()
+            }
+            RecordDelimiter::RecordStart(pattern) => group_lines_stream(
+                Box::pin(split_offsets_stream(file, b"\n".to_vec(), start)),
+                pattern.clone(),
+            )
+            .right_stream(),
+        };
+        let tracked = offset.clone();
+        let stream = lines.map_ok(move |(s, end)| {
+            tracked.set(end);
+            s
+        });
+        Ok((Box::pin(stream), offset))
+    }
+
+    async fn lines(&self) -> io::Result<Pin<Box<dyn Stream<Item = io::Result<String>> + Send>>> {
         match self {
             RecordsIn::File(path) => {
                 let file = File::open(path).await?;
@@ -58,6 +197,67 @@ impl RecordsIn {
             RecordsIn::Stdin => Ok(Box::pin(stdio_lines_stream(std::io::stdin()))),
         }
     }
+
+    /// Raw bytes, for formats that are not line-delimited.
+    pub async fn byte_reader(&self) -> io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        match self {
+            RecordsIn::File(path) => {
+                let file = File::open(path).await?;
+                Ok(Box::new(tokio::io::BufReader::new(file)))
+            }
+            RecordsIn::Stdin => Ok(Box::new(tokio::io::BufReader::new(tokio::io::stdin()))),
+        }
+    }
+
+    /// Like [`Self::byte_reader`], but starting at a byte offset into a file.
+    /// The returned offset tracks the bytes consumed.
+    pub async fn byte_reader_at(
+        &self,
+        start: u64,
+    ) -> io::Result<(Box<dyn AsyncRead + Send + Unpin>, InputOffset)> {
+        let offset = InputOffset::new(start);
+        let reader = CountingReader {
+            inner: tokio::io::BufReader::new(self.file_at(start).await?),
+            offset: offset.clone(),
+        };
+        Ok((Box::new(reader), offset))
+    }
+
+    async fn file_at(&self, start: u64) -> io::Result<File> {
+        match self {
+            RecordsIn::File(path) => {
+                let mut file = File::open(path).await?;
+                file.seek(io::SeekFrom::Start(start)).await?;
+                Ok(file)
+            }
+            RecordsIn::Stdin => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "input must be a file to resume from an offset",
+            )),
+        }
+    }
+}
+
+/// Byte offset into an input, shared between a reader and its consumer.
+#[derive(Debug, Clone)]
+pub struct InputOffset(Arc<AtomicU64>);
+
+impl InputOffset {
+    fn new(offset: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(offset)))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, offset: u64) {
+        self.0.store(offset, Ordering::Relaxed);
+    }
+
+    fn advance(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
 }
 
 impl RecordsOut {
@@ -82,6 +282,47 @@ impl RecordsOut {
     }
 }
 
+/// Decoded command record.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum CommandRecord {
+    Fence { token: String },
+    Trim { trim_point: u64 },
+}
+
+impl CommandRecord {
+    /// Decode a command record, if it is one of the known commands.
+    pub fn from_record(record: &SequencedRecord) -> Option<Self> {
+        if !record.is_command_record() {
+            return None;
+        }
+        Self::decode(&record.headers[0].value, &record.body)
+    }
+
+    fn decode(command: &[u8], body: &[u8]) -> Option<Self> {
+        match command {
+            b"fence" => Some(Self::Fence {
+                token: String::from_utf8_lossy(body).into_owned(),
+            }),
+            b"trim" => Some(Self::Trim {
+                trim_point: body
+                    .get(..8)
+                    .map_or(0, |b| u64::from_be_bytes(b.try_into().expect("8 bytes"))),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CommandRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fence { token } => write!(f, "new fencing token \"{token}\""),
+            Self::Trim { trim_point } => write!(f, "trim to {trim_point}"),
+        }
+    }
+}
+
 fn stdio_lines_stream<F>(f: F) -> ReceiverStream<io::Result<String>>
 where
     F: std::io::Read + Send + 'static,
@@ -98,6 +339,118 @@ where
     ReceiverStream::new(rx)
 }
 
+fn utf8(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Split raw input on a byte sequence. A trailing delimiter does not produce an empty record.
+fn split_stream<R>(reader: R, delimiter: Vec<u8>) -> impl Stream<Item = io::Result<String>>
+where
+    R: AsyncRead + Send + Unpin,
+{
+    split_offsets_stream(reader, delimiter, 0).map_ok(|(s, _)| s)
+}
+
+/// Like [`split_stream`], also yielding the input offset just past each delimiter,
+/// counting from `start`. A newline delimiter also strips a preceding carriage return.
+fn split_offsets_stream<R>(
+    mut reader: R,
+    delimiter: Vec<u8>,
+    start: u64,
+) -> impl Stream<Item = io::Result<(String, u64)>>
+where
+    R: AsyncRead + Send + Unpin,
+{
+    async_stream::try_stream! {
+        let mut buf = BytesMut::new();
+        let mut offset = start;
+        // Position up to which `buf` is known not to contain the delimiter.
+        let mut searched = 0;
+        loop {
+            if let Some(pos) = buf[searched..]
+                .windows(delimiter.len())
+                .position(|w| w == delimiter.as_slice())
+            {
+                let end = searched + pos;
+                // Splitting off the front of `buf` does not copy the rest of it.
+                let mut record = buf.split_to(end + delimiter.len());
+                offset += record.len() as u64;
+                record.truncate(end);
+                if delimiter == b"\n" && record.last() == Some(&b'\r') {
+                    record.truncate(end - 1);
+                }
+                yield (utf8(record.to_vec())?, offset);
+                searched = 0;
+                continue;
+            }
+            searched = buf.len().saturating_sub(delimiter.len() - 1);
+
+            buf.reserve(64 * 1024);
+            if reader.read_buf(&mut buf).await? == 0 {
+                if !buf.is_empty() {
+                    offset += buf.len() as u64;
+                    yield (utf8(buf.to_vec())?, offset);
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Group lines into records, each starting at a line matching `pattern`.
+/// Any lines before the first match form a record of their own.
+/// Each record carries the `tag` of its last line.
+fn group_lines_stream<S, T>(
+    mut lines: S,
+    pattern: Regex,
+) -> impl Stream<Item = io::Result<(String, T)>>
+where
+    S: Stream<Item = io::Result<(String, T)>> + Send + Unpin,
+{
+    async_stream::try_stream! {
+        let mut record: Option<(String, T)> = None;
+        while let Some(line) = lines.next().await {
+            let (line, tag) = line?;
+            match record.as_mut() {
+                Some((current, current_tag)) if !pattern.is_match(&line) => {
+                    current.push('\n');
+                    current.push_str(&line);
+                    *current_tag = tag;
+                }
+                _ => {
+                    if let Some(done) = record.replace((line, tag)) {
+                        yield done;
+                    }
+                }
+            }
+        }
+        if let Some(done) = record {
+            yield done;
+        }
+    }
+}
+
+/// Count the bytes read through an [`AsyncRead`] into an [`InputOffset`].
+struct CountingReader<R> {
+    inner: R,
+    offset: InputOffset,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            self.offset.advance((buf.filled().len() - before) as u64);
+        }
+        res
+    }
+}
+
 pub fn parse_records_input_source(s: &str) -> Result<RecordsIn, io::Error> {
     match s {
         "" | "-" => Ok(RecordsIn::Stdin),
@@ -112,23 +465,99 @@ pub fn parse_records_output_source(s: &str) -> Result<RecordsOut, io::Error> {
     }
 }
 
+/// A record parsed from input, before it is validated as an [`AppendRecord`].
+#[derive(Debug, Clone)]
+pub struct ParsedRecord {
+    pub headers: Vec<Header>,
+    pub body: Bytes,
+    pub timestamp: Option<u64>,
+}
+
+impl ParsedRecord {
+    pub fn new(body: impl Into<Bytes>) -> Self {
+        Self {
+            headers: Vec::new(),
+            body: body.into(),
+            timestamp: None,
+        }
+    }
+
+    /// Whether this is a command record, i.e. has a single header with an empty name.
+    pub fn is_command_record(&self) -> bool {
+        self.headers.len() == 1 && self.headers[0].name.is_empty()
+    }
+}
+
+impl TryFrom<ParsedRecord> for AppendRecord {
+    type Error = RecordParseError;
+
+    fn try_from(value: ParsedRecord) -> Result<Self, Self::Error> {
+        let ParsedRecord {
+            headers,
+            body,
+            timestamp,
+        } = value;
+        let mut record =
+            AppendRecord::new(body).map_err(|e| RecordParseError::Parse(e.to_string()))?;
+        if !headers.is_empty() {
+            record = record
+                .with_headers(headers)
+                .map_err(|e| RecordParseError::Parse(e.to_string()))?;
+        }
+        if let Some(ts) = timestamp {
+            record = record.with_timestamp(ts);
+        }
+        Ok(record)
+    }
+}
+
+/// Stream validating each [`ParsedRecord`] of `S` as an [`AppendRecord`].
+pub struct AppendRecords<S>(pub S);
+
+impl<S> Stream for AppendRecords<S>
+where
+    S: Stream<Item = Result<ParsedRecord, RecordParseError>> + Send + Unpin,
+{
+    type Item = Result<AppendRecord, RecordParseError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .poll_next_unpin(cx)
+            .map(|record| record.map(|record| record?.try_into()))
+    }
+}
+
 pub trait RecordParser<I>
 where
     I: Stream<Item = io::Result<String>> + Send + Unpin,
 {
-    type RecordStream: Stream<Item = Result<AppendRecord, RecordParseError>> + Send + Unpin;
+    type RecordStream: Stream<Item = Result<ParsedRecord, RecordParseError>> + Send + Unpin;
 
-    fn parse_records(lines: I) -> Self::RecordStream;
+    /// Parse records without validating them.
+    fn parse(lines: I) -> Self::RecordStream;
+
+    fn parse_records(lines: I) -> AppendRecords<Self::RecordStream> {
+        AppendRecords(Self::parse(lines))
+    }
 }
 
 pub trait RecordWriter {
+    /// Write anything that should precede the first record.
+    async fn write_preamble(&self, _writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        Ok(())
+    }
+
     async fn write_record(
+        &self,
         record: &SequencedRecord,
         writer: &mut (impl AsyncWrite + Unpin),
     ) -> io::Result<()>;
 }
 
+pub use binary::BinaryFormatter;
 pub use body::TextFormatter;
+pub use csv::{CsvColumn, CsvFormatter};
+pub use template::TemplateFormatter;
 pub type JsonFormatter = json::Formatter<false>;
 pub type JsonBase64Formatter = json::Formatter<true>;
 
@@ -140,15 +569,16 @@ mod body {
     };
 
     use futures::{Stream, StreamExt};
-    use s2_sdk::types::{AppendRecord, SequencedRecord};
+    use s2_sdk::types::SequencedRecord;
     use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-    use super::{RecordParseError, RecordParser, RecordWriter};
+    use super::{ParsedRecord, RecordParseError, RecordParser, RecordWriter};
 
     pub struct TextFormatter;
 
     impl RecordWriter for TextFormatter {
         async fn write_record(
+            &self,
             record: &SequencedRecord,
             writer: &mut (impl AsyncWrite + Unpin),
         ) -> io::Result<()> {
@@ -163,7 +593,7 @@ mod body {
     {
         type RecordStream = RecordStream<I>;
 
-        fn parse_records(lines: I) -> Self::RecordStream {
+        fn parse(lines: I) -> Self::RecordStream {
             RecordStream(lines)
         }
     }
@@ -174,16 +604,14 @@ mod body {
     where
         S: Stream<Item = io::Result<String>> + Send + Unpin,
     {
-        type Item = Result<AppendRecord, RecordParseError>;
+        type Item = Result<ParsedRecord, RecordParseError>;
 
         fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
             match self.0.poll_next_unpin(cx) {
                 Poll::Pending => Poll::Pending,
                 Poll::Ready(None) => Poll::Ready(None),
                 Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
-                Poll::Ready(Some(Ok(s))) => Poll::Ready(Some(
-                    AppendRecord::new(s).map_err(|e| RecordParseError::Parse(e.to_string())),
-                )),
+                Poll::Ready(Some(Ok(s))) => Poll::Ready(Some(Ok(ParsedRecord::new(s)))),
             }
         }
     }
@@ -200,11 +628,11 @@ mod json {
     use base64ct::{Base64, Encoding};
     use bytes::Bytes;
     use futures::{Stream, StreamExt};
-    use s2_sdk::types::{AppendRecord, Header, SequencedRecord};
+    use s2_sdk::types::{Header, SequencedRecord};
     use serde::{Deserialize, Serialize};
     use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-    use super::{RecordParseError, RecordParser, RecordWriter};
+    use super::{CommandRecord, ParsedRecord, RecordParseError, RecordParser, RecordWriter};
 
     #[derive(Debug, Clone, Default)]
     struct CowStr<'a, const BIN_SAFE: bool>(Cow<'a, str>);
@@ -264,12 +692,16 @@ mod json {
 
     #[derive(Debug, Clone, Serialize)]
     struct SerializableSequencedRecord<'a, const BIN_SAFE: bool> {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stream: Option<&'a str>,
         seq_num: u64,
         timestamp: u64,
         #[serde(skip_serializing_if = "Vec::is_empty")]
         headers: Vec<(CowStr<'a, BIN_SAFE>, CowStr<'a, BIN_SAFE>)>,
         #[serde(skip_serializing_if = "CowStr::is_empty")]
         body: CowStr<'a, BIN_SAFE>,
+        #[serde(flatten, skip_serializing_if = "Option::is_none")]
+        command: Option<CommandRecord>,
     }
 
     impl<'a, const BIN_SAFE: bool> From<&'a SequencedRecord>
@@ -292,16 +724,46 @@ mod json {
             let body: CowStr<BIN_SAFE> = body.as_ref().into();
 
             SerializableSequencedRecord {
+                stream: None,
                 timestamp: *timestamp,
                 seq_num: *seq_num,
                 headers,
                 body,
+                command: CommandRecord::from_record(value),
             }
         }
     }
 
+    impl<const BIN_SAFE: bool> Formatter<BIN_SAFE> {
+        /// Write a record with a `stream` field naming the stream it was read from.
+        pub async fn write_sourced_record(
+            &self,
+            record: &SequencedRecord,
+            stream: &str,
+            writer: &mut (impl AsyncWrite + Unpin),
+        ) -> io::Result<()> {
+            let record = SerializableSequencedRecord::<BIN_SAFE> {
+                stream: Some(stream),
+                ..record.into()
+            };
+            let s = serde_json::to_string(&record).map_err(io::Error::other)?;
+            writer.write_all(s.as_bytes()).await
+        }
+
+        /// Serialize records as a JSON array.
+        pub fn serialize_batch<'a>(
+            &self,
+            records: impl IntoIterator<Item = &'a SequencedRecord>,
+        ) -> serde_json::Result<Vec<u8>> {
+            let records: Vec<SerializableSequencedRecord<BIN_SAFE>> =
+                records.into_iter().map(Into::into).collect();
+            serde_json::to_vec(&records)
+        }
+    }
+
     impl<const BIN_SAFE: bool> RecordWriter for Formatter<BIN_SAFE> {
         async fn write_record(
+            &self,
             record: &SequencedRecord,
             writer: &mut (impl AsyncWrite + Unpin),
         ) -> io::Result<()> {
@@ -317,7 +779,7 @@ mod json {
     {
         type RecordStream = RecordStream<BIN_SAFE, I>;
 
-        fn parse_records(lines: I) -> Self::RecordStream {
+        fn parse(lines: I) -> Self::RecordStream {
             RecordStream(lines)
         }
     }
@@ -331,7 +793,7 @@ mod json {
         body: OwnedCowStr<BIN_SAFE>,
     }
 
-    impl<const BIN_SAFE: bool> TryFrom<DeserializableAppendRecord<BIN_SAFE>> for AppendRecord {
+    impl<const BIN_SAFE: bool> TryFrom<DeserializableAppendRecord<BIN_SAFE>> for ParsedRecord {
         type Error = String;
 
         fn try_from(value: DeserializableAppendRecord<BIN_SAFE>) -> Result<Self, Self::Error> {
@@ -341,28 +803,20 @@ mod json {
                 body,
             } = value;
 
-            let body_bytes: Bytes = body.try_into()?;
-            let mut record = AppendRecord::new(body_bytes).map_err(|e| e.to_string())?;
-
-            if !headers.is_empty() {
-                let parsed_headers: Vec<Header> = headers
-                    .into_iter()
-                    .map(|(name, value)| {
-                        let name_bytes: Bytes = name.try_into()?;
-                        let value_bytes: Bytes = value.try_into()?;
-                        Ok(Header::new(name_bytes, value_bytes))
-                    })
-                    .collect::<Result<Vec<_>, String>>()?;
-                record = record
-                    .with_headers(parsed_headers)
-                    .map_err(|e| e.to_string())?;
-            }
-
-            if let Some(ts) = timestamp {
-                record = record.with_timestamp(ts);
-            }
+            let headers = headers
+                .into_iter()
+                .map(|(name, value)| {
+                    let name_bytes: Bytes = name.try_into()?;
+                    let value_bytes: Bytes = value.try_into()?;
+                    Ok(Header::new(name_bytes, value_bytes))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
 
-            Ok(record)
+            Ok(ParsedRecord {
+                headers,
+                body: body.try_into()?,
+                timestamp,
+            })
         }
     }
 
@@ -372,12 +826,12 @@ mod json {
     where
         S: Stream<Item = io::Result<String>> + Send + Unpin,
     {
-        type Item = Result<AppendRecord, RecordParseError>;
+        type Item = Result<ParsedRecord, RecordParseError>;
 
         fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
             fn parse_record<const BIN_SAFE: bool>(
                 s: String,
-            ) -> Result<AppendRecord, RecordParseError> {
+            ) -> Result<ParsedRecord, RecordParseError> {
                 let append_record: DeserializableAppendRecord<BIN_SAFE> =
                     serde_json::from_str(&s).map_err(|e| RecordParseError::Parse(e.to_string()))?;
 
@@ -393,3 +847,870 @@ mod json {
         }
     }
 }
+
+mod csv {
+    use std::{
+        fmt, io,
+        pin::Pin,
+        str::FromStr,
+        task::{Context, Poll, ready},
+    };
+
+    use futures::{Stream, StreamExt};
+    use s2_sdk::types::{Header, SequencedRecord};
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    use super::{ParsedRecord, RecordParseError, RecordParser, RecordWriter};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum CsvColumn {
+        SeqNum,
+        Timestamp,
+        Body,
+        Header(String),
+    }
+
+    impl FromStr for CsvColumn {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(match s {
+                "seq_num" => Self::SeqNum,
+                "timestamp" => Self::Timestamp,
+                "body" => Self::Body,
+                _ => {
+                    let name = s.strip_prefix("header:").unwrap_or(s);
+                    if name.is_empty() {
+                        return Err(format!("invalid CSV column: {s:?}"));
+                    }
+                    Self::Header(name.to_owned())
+                }
+            })
+        }
+    }
+
+    impl fmt::Display for CsvColumn {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::SeqNum => write!(f, "seq_num"),
+                Self::Timestamp => write!(f, "timestamp"),
+                Self::Body => write!(f, "body"),
+                Self::Header(name) if matches!(name.as_str(), "seq_num" | "timestamp" | "body") => {
+                    write!(f, "header:{name}")
+                }
+                Self::Header(name) => write!(f, "{name}"),
+            }
+        }
+    }
+
+    pub struct CsvFormatter {
+        columns: Vec<CsvColumn>,
+    }
+
+    impl CsvFormatter {
+        pub fn new(columns: Vec<CsvColumn>) -> Self {
+            Self { columns }
+        }
+    }
+
+    fn write_row<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> String {
+        let mut row = String::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                row.push(',');
+            }
+            let field = field.as_ref();
+            if field.contains([',', '"', '\r', '\n']) {
+                row.push('"');
+                row.push_str(&field.replace('"', "\"\""));
+                row.push('"');
+            } else {
+                row.push_str(field);
+            }
+        }
+        row
+    }
+
+    fn split_row(row: &str) -> Result<Vec<String>, RecordParseError> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut chars = row.chars().peekable();
+        let mut quoted = false;
+
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') if field.is_empty() => quoted = true,
+                (false, ',') => fields.push(std::mem::take(&mut field)),
+                (false, c) => field.push(c),
+            }
+        }
+        if quoted {
+            return Err(RecordParseError::Parse(
+                "unterminated quoted CSV field".to_owned(),
+            ));
+        }
+        fields.push(field);
+        Ok(fields)
+    }
+
+    impl RecordWriter for CsvFormatter {
+        async fn write_preamble(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+            let row = write_row(self.columns.iter().map(ToString::to_string));
+            writer.write_all(row.as_bytes()).await?;
+            writer.write_all(b"\n").await
+        }
+
+        async fn write_record(
+            &self,
+            record: &SequencedRecord,
+            writer: &mut (impl AsyncWrite + Unpin),
+        ) -> io::Result<()> {
+            let row = write_row(self.columns.iter().map(|column| {
+                match column {
+                    CsvColumn::SeqNum => record.seq_num.to_string(),
+                    CsvColumn::Timestamp => record.timestamp.to_string(),
+                    CsvColumn::Body => String::from_utf8_lossy(&record.body).into_owned(),
+                    CsvColumn::Header(name) => record
+                        .headers
+                        .iter()
+                        .find(|h| h.name.as_ref() == name.as_bytes())
+                        .map(|h| String::from_utf8_lossy(&h.value).into_owned())
+                        .unwrap_or_default(),
+                }
+            }));
+            writer.write_all(row.as_bytes()).await
+        }
+    }
+
+    impl<I> RecordParser<I> for CsvFormatter
+    where
+        I: Stream<Item = io::Result<String>> + Send + Unpin,
+    {
+        type RecordStream = RecordStream<I>;
+
+        fn parse(lines: I) -> Self::RecordStream {
+            RecordStream {
+                lines,
+                columns: None,
+                pending: None,
+            }
+        }
+    }
+
+    pub struct RecordStream<S> {
+        lines: S,
+        columns: Option<Vec<CsvColumn>>,
+        /// Partial row spanning multiple lines due to a quoted newline.
+        pending: Option<String>,
+    }
+
+    fn parse_record(
+        columns: &[CsvColumn],
+        fields: Vec<String>,
+    ) -> Result<ParsedRecord, RecordParseError> {
+        if fields.len() != columns.len() {
+            return Err(RecordParseError::Parse(format!(
+                "expected {} CSV fields, found {}",
+                columns.len(),
+                fields.len()
+            )));
+        }
+
+        let mut body = String::new();
+        let mut timestamp = None;
+        let mut headers = Vec::new();
+        for (column, field) in columns.iter().zip(fields) {
+            match column {
+                CsvColumn::SeqNum => {}
+                CsvColumn::Timestamp if field.is_empty() => {}
+                CsvColumn::Timestamp => {
+                    timestamp = Some(field.parse::<u64>().map_err(|_| {
+                        RecordParseError::Parse(format!("invalid timestamp: {field:?}"))
+                    })?);
+                }
+                CsvColumn::Body => body = field,
+                CsvColumn::Header(_) if field.is_empty() => {}
+                CsvColumn::Header(name) => headers.push(Header::new(name.clone(), field)),
+            }
+        }
+
+        Ok(ParsedRecord {
+            headers,
+            body: body.into(),
+            timestamp,
+        })
+    }
+
+    impl<S> Stream for RecordStream<S>
+    where
+        S: Stream<Item = io::Result<String>> + Send + Unpin,
+    {
+        type Item = Result<ParsedRecord, RecordParseError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            loop {
+                let line = match ready!(self.lines.poll_next_unpin(cx)) {
+                    None if self.pending.take().is_some() => {
+                        return Poll::Ready(Some(Err(RecordParseError::Parse(
+                            "unterminated quoted CSV field".to_owned(),
+                        ))));
+                    }
+                    None => return Poll::Ready(None),
+                    Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                    Some(Ok(line)) => line,
+                };
+
+                let row = match self.pending.take() {
+                    Some(mut pending) => {
+                        pending.push('\n');
+                        pending.push_str(&line);
+                        pending
+                    }
+                    None => line,
+                };
+                // An odd number of quotes means a quoted field continues on the next line.
+                if row.matches('"').count() % 2 == 1 {
+                    self.pending = Some(row);
+                    continue;
+                }
+
+                let fields = match split_row(&row) {
+                    Ok(fields) => fields,
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                };
+                match &self.columns {
+                    Some(columns) => return Poll::Ready(Some(parse_record(columns, fields))),
+                    None => {
+                        let columns = fields
+                            .iter()
+                            .map(|f| f.parse::<CsvColumn>())
+                            .collect::<Result<Vec<_>, _>>();
+                        match columns {
+                            Ok(columns) => self.columns = Some(columns),
+                            Err(e) => return Poll::Ready(Some(Err(RecordParseError::Parse(e)))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{CsvColumn, split_row, write_row};
+
+        #[test]
+        fn test_csv_row_roundtrip() {
+            let fields = ["plain", "with,comma", "with \"quote\"", "multi\nline", ""];
+            let row = write_row(fields);
+            assert_eq!(
+                row,
+                "plain,\"with,comma\",\"with \"\"quote\"\"\",\"multi\nline\","
+            );
+            assert_eq!(split_row(&row).unwrap(), fields);
+            assert!(split_row("\"unterminated").is_err());
+        }
+
+        #[test]
+        fn test_csv_column_parse() {
+            assert_eq!("seq_num".parse(), Ok(CsvColumn::SeqNum));
+            assert_eq!("user".parse(), Ok(CsvColumn::Header("user".to_owned())));
+            assert_eq!(
+                "header:body".parse::<CsvColumn>().map(|c| c.to_string()),
+                Ok("header:body".to_owned())
+            );
+            assert!("header:".parse::<CsvColumn>().is_err());
+        }
+    }
+}
+
+mod binary {
+    use std::io;
+
+    use bytes::Bytes;
+    use futures::Stream;
+    use s2_sdk::types::{AppendRecord, Header, RECORD_BATCH_MAX, SequencedRecord};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::{AppendRecords, ParsedRecord, RecordParseError, RecordWriter};
+
+    pub struct BinaryFormatter;
+
+    fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+        while n >= 0x80 {
+            buf.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        buf.push(n as u8);
+    }
+
+    fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+        put_varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+
+    fn encode_frame(seq_num: u64, timestamp: u64, headers: &[Header], body: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(body.len() + 32);
+        put_varint(&mut buf, seq_num);
+        put_varint(&mut buf, timestamp);
+        put_varint(&mut buf, headers.len() as u64);
+        for header in headers {
+            put_bytes(&mut buf, &header.name);
+            put_bytes(&mut buf, &header.value);
+        }
+        put_bytes(&mut buf, body);
+        buf
+    }
+
+    impl RecordWriter for BinaryFormatter {
+        async fn write_record(
+            &self,
+            record: &SequencedRecord,
+            writer: &mut (impl AsyncWrite + Unpin),
+        ) -> io::Result<()> {
+            let frame = encode_frame(
+                record.seq_num,
+                record.timestamp,
+                &record.headers,
+                &record.body,
+            );
+            writer.write_all(&frame).await
+        }
+    }
+
+    async fn read_varint(first: u8, reader: &mut (impl AsyncRead + Unpin)) -> io::Result<u64> {
+        let mut n = 0u64;
+        let mut byte = first;
+        for shift in (0..64).step_by(7) {
+            // Only the lowest bit of the 10th byte fits, and it must be the last.
+            if shift == 63 && byte > 1 {
+                break;
+            }
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            byte = reader.read_u8().await?;
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint overflow",
+        ))
+    }
+
+    async fn read_len(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<usize> {
+        let first = reader.read_u8().await?;
+        let len = read_varint(first, reader).await?;
+        usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= RECORD_BATCH_MAX.bytes)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("length {len} exceeds {}", RECORD_BATCH_MAX.bytes),
+                )
+            })
+    }
+
+    async fn read_bytes(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Bytes> {
+        let mut buf = vec![0; read_len(reader).await?];
+        reader.read_exact(&mut buf).await?;
+        Ok(buf.into())
+    }
+
+    /// Read the next frame, or `None` if the input ends at a frame boundary.
+    async fn read_frame(
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<Option<ParsedRecord>, RecordParseError> {
+        let mut first = [0u8];
+        if reader.read(&mut first).await? == 0 {
+            return Ok(None);
+        }
+
+        let frame = async {
+            let _seq_num = read_varint(first[0], reader).await?;
+            let timestamp = read_varint(reader.read_u8().await?, reader).await?;
+            let header_count = read_len(reader).await?;
+            let mut headers = Vec::with_capacity(header_count.min(64));
+            for _ in 0..header_count {
+                let name = read_bytes(reader).await?;
+                let value = read_bytes(reader).await?;
+                headers.push(Header::new(name, value));
+            }
+            let body = read_bytes(reader).await?;
+            io::Result::Ok((timestamp, headers, body))
+        };
+        let (timestamp, headers, body) = frame.await.map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => RecordParseError::Parse("truncated frame".to_owned()),
+            io::ErrorKind::InvalidData => RecordParseError::Parse(e.to_string()),
+            _ => e.into(),
+        })?;
+
+        Ok(Some(ParsedRecord {
+            headers,
+            body,
+            timestamp: Some(timestamp),
+        }))
+    }
+
+    impl BinaryFormatter {
+        /// Parse records without validating them.
+        pub fn parse<R>(
+            mut reader: R,
+        ) -> impl Stream<Item = Result<ParsedRecord, RecordParseError>> + Send + Unpin
+        where
+            R: AsyncRead + Send + Unpin,
+        {
+            Box::pin(async_stream::stream! {
+                loop {
+                    match read_frame(&mut reader).await {
+                        Ok(Some(record)) => yield Ok(record),
+                        Ok(None) => break,
+                        Err(e) => {
+                            yield Err(e);
+                            break;
+                        }
+                    }
+                }
+            })
+        }
+
+        pub fn parse_records<R>(
+            reader: R,
+        ) -> impl Stream<Item = Result<AppendRecord, RecordParseError>> + Send + Unpin
+        where
+            R: AsyncRead + Send + Unpin,
+        {
+            AppendRecords(Self::parse(reader))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use futures::{StreamExt, TryStreamExt};
+        use s2_sdk::types::{AppendRecord, Header};
+
+        use super::{BinaryFormatter, encode_frame, put_varint, read_varint};
+
+        #[tokio::test]
+        async fn test_varint_roundtrip() {
+            for n in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+                let mut buf = Vec::new();
+                put_varint(&mut buf, n);
+                let mut reader = &buf[1..];
+                assert_eq!(read_varint(buf[0], &mut reader).await.unwrap(), n);
+                assert!(reader.is_empty());
+            }
+
+            // An 11th byte, or bits beyond 64 in the 10th byte, overflow.
+            for overlong in [
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02][..],
+                &[
+                    0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x81, 0x00,
+                ][..],
+            ] {
+                let mut reader = &overlong[1..];
+                assert!(read_varint(overlong[0], &mut reader).await.is_err());
+            }
+        }
+
+        #[tokio::test]
+        async fn test_frame_roundtrip() {
+            let headers = vec![
+                Header::new("", "fence"),
+                Header::new("k\n", vec![0u8, 0xff, b'\n']),
+            ];
+            let body = vec![0u8, b'\n', 0xfe, b'\r'];
+            let mut input = encode_frame(7, 1000, &headers, &body);
+            input.extend(encode_frame(8, 1001, &[], b""));
+
+            let records: Vec<_> = BinaryFormatter::parse_records(input.as_slice())
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                records,
+                vec![
+                    AppendRecord::new(body)
+                        .unwrap()
+                        .with_headers(headers)
+                        .unwrap()
+                        .with_timestamp(1000),
+                    AppendRecord::new("").unwrap().with_timestamp(1001),
+                ]
+            );
+
+            let truncated = &input[..input.len() - 1];
+            let results: Vec<_> = BinaryFormatter::parse_records(truncated).collect().await;
+            assert_eq!(results.len(), 2);
+            assert!(results[1].is_err());
+        }
+    }
+}
+
+mod template {
+    use std::{
+        io,
+        str::FromStr,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use base64ct::{Base64, Encoding as _};
+    use s2_sdk::types::SequencedRecord;
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    use super::{RecordWriter, unescape};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Encoding {
+        /// UTF-8, with lossy decoding.
+        Utf8,
+        /// ASCII with control characters, quotes and non-ASCII bytes escaped.
+        Escape,
+        /// Quoted JSON string, with lossy decoding.
+        Json,
+        Hex,
+        Base64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Field {
+        SeqNum,
+        Timestamp { rfc3339: bool },
+        Body(Encoding),
+        Header(String, Encoding),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Segment {
+        Literal(Vec<u8>),
+        Field(Field),
+    }
+
+    /// Renders each record according to a template such as
+    /// `{seq_num}\t{timestamp:rfc3339}\t{header.user}\t{body:escape}`.
+    #[derive(Debug, Clone)]
+    pub struct TemplateFormatter {
+        segments: Vec<Segment>,
+    }
+
+    impl FromStr for Encoding {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(match s {
+                "" | "utf8" => Self::Utf8,
+                "escape" => Self::Escape,
+                "json" => Self::Json,
+                "hex" => Self::Hex,
+                "base64" => Self::Base64,
+                _ => return Err(format!("unknown encoding {s:?}")),
+            })
+        }
+    }
+
+    impl FromStr for Field {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (name, modifier) = s.split_once(':').unwrap_or((s, ""));
+            Ok(match name {
+                "seq_num" if modifier.is_empty() => Self::SeqNum,
+                "timestamp" => match modifier {
+                    "" | "ms" => Self::Timestamp { rfc3339: false },
+                    "rfc3339" => Self::Timestamp { rfc3339: true },
+                    _ => return Err(format!("unknown timestamp format {modifier:?}")),
+                },
+                "body" => Self::Body(modifier.parse()?),
+                _ => match name.strip_prefix("header.") {
+                    Some(header) => Self::Header(header.to_owned(), modifier.parse()?),
+                    None => return Err(format!("unknown field {s:?}")),
+                },
+            })
+        }
+    }
+
+    impl FromStr for TemplateFormatter {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut segments = Vec::new();
+            let mut literal = String::new();
+            let mut chars = s.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '{' if chars.peek() == Some(&'{') => {
+                        chars.next();
+                        literal.push('{');
+                    }
+                    '}' if chars.peek() == Some(&'}') => {
+                        chars.next();
+                        literal.push('}');
+                    }
+                    '{' => {
+                        let mut field = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('}') => break,
+                                Some(c) => field.push(c),
+                                None => return Err("unterminated field".to_owned()),
+                            }
+                        }
+                        if !literal.is_empty() {
+                            segments
+                                .push(Segment::Literal(unescape(&std::mem::take(&mut literal))?));
+                        }
+                        segments.push(Segment::Field(field.trim().parse()?));
+                    }
+                    '}' => return Err("unmatched '}', use '}}' for a literal brace".to_owned()),
+                    c => literal.push(c),
+                }
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(unescape(&literal)?));
+            }
+            Ok(Self { segments })
+        }
+    }
+
+    fn encode(bytes: &[u8], encoding: Encoding, out: &mut Vec<u8>) {
+        match encoding {
+            Encoding::Utf8 => out.extend_from_slice(String::from_utf8_lossy(bytes).as_bytes()),
+            Encoding::Escape => out.extend(bytes.escape_ascii()),
+            Encoding::Json => {
+                let s = serde_json::to_string(&String::from_utf8_lossy(bytes))
+                    .expect("strings serialize");
+                out.extend_from_slice(s.as_bytes());
+            }
+            Encoding::Hex => {
+                for b in bytes {
+                    out.extend_from_slice(format!("{b:02x}").as_bytes());
+                }
+            }
+            Encoding::Base64 => out.extend_from_slice(Base64::encode_string(bytes).as_bytes()),
+        }
+    }
+
+    impl TemplateFormatter {
+        fn render(&self, record: &SequencedRecord) -> Vec<u8> {
+            let mut out = Vec::new();
+            for segment in &self.segments {
+                match segment {
+                    Segment::Literal(bytes) => out.extend_from_slice(bytes),
+                    Segment::Field(Field::SeqNum) => {
+                        out.extend_from_slice(record.seq_num.to_string().as_bytes())
+                    }
+                    Segment::Field(Field::Timestamp { rfc3339: false }) => {
+                        out.extend_from_slice(record.timestamp.to_string().as_bytes())
+                    }
+                    Segment::Field(Field::Timestamp { rfc3339: true }) => {
+                        let time = UNIX_EPOCH + Duration::from_millis(record.timestamp);
+                        out.extend_from_slice(
+                            humantime::format_rfc3339_millis(time)
+                                .to_string()
+                                .as_bytes(),
+                        );
+                    }
+                    Segment::Field(Field::Body(encoding)) => {
+                        encode(&record.body, *encoding, &mut out)
+                    }
+                    Segment::Field(Field::Header(name, encoding)) => {
+                        if let Some(header) = record
+                            .headers
+                            .iter()
+                            .find(|h| h.name.as_ref() == name.as_bytes())
+                        {
+                            encode(&header.value, *encoding, &mut out);
+                        }
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    impl RecordWriter for TemplateFormatter {
+        async fn write_record(
+            &self,
+            record: &SequencedRecord,
+            writer: &mut (impl AsyncWrite + Unpin),
+        ) -> io::Result<()> {
+            writer.write_all(&self.render(record)).await
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{Encoding, Field, Segment, TemplateFormatter, encode};
+
+        #[test]
+        fn test_template_parse() {
+            let template: TemplateFormatter =
+                "{seq_num}\\t{timestamp:rfc3339} {{{header.user:hex}}}"
+                    .parse()
+                    .unwrap();
+            assert_eq!(
+                template.segments,
+                vec![
+                    Segment::Field(Field::SeqNum),
+                    Segment::Literal(b"\t".to_vec()),
+                    Segment::Field(Field::Timestamp { rfc3339: true }),
+                    Segment::Literal(b" {".to_vec()),
+                    Segment::Field(Field::Header("user".to_owned(), Encoding::Hex)),
+                    Segment::Literal(b"}".to_vec()),
+                ]
+            );
+
+            for invalid in ["{body", "body}", "{seq_num:hex}", "{body:rot13}", "{user}"] {
+                assert!(invalid.parse::<TemplateFormatter>().is_err(), "{invalid}");
+            }
+        }
+
+        #[test]
+        fn test_template_encode() {
+            let encoded = |encoding| {
+                let mut out = Vec::new();
+                encode(b"a\"\n\xff", encoding, &mut out);
+                String::from_utf8(out).unwrap()
+            };
+            assert_eq!(encoded(Encoding::Utf8), "a\"\n\u{fffd}");
+            assert_eq!(encoded(Encoding::Escape), "a\\\"\\n\\xff");
+            assert_eq!(encoded(Encoding::Json), "\"a\\\"\\n\u{fffd}\"");
+            assert_eq!(encoded(Encoding::Hex), "61220aff");
+            assert_eq!(encoded(Encoding::Base64), "YSIK/w==");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, TryStreamExt, stream};
+
+    use super::{CommandRecord, RecordDelimiter, RecordsIn, group_lines_stream, split_stream};
+
+    #[test]
+    fn test_delimiter_parse() {
+        let bytes = |s: &str| match s.parse::<RecordDelimiter>() {
+            Ok(RecordDelimiter::Bytes(bytes)) => Ok(bytes),
+            Ok(RecordDelimiter::RecordStart(_)) => Err("unexpected pattern".to_owned()),
+            Err(e) => Err(e),
+        };
+        assert_eq!(bytes("\\0"), Ok(vec![0]));
+        assert_eq!(bytes("--\\r\\n"), Ok(b"--\r\n".to_vec()));
+        assert_eq!(bytes("\\x1e\\\\"), Ok(vec![0x1e, b'\\']));
+        assert!(bytes("").is_err());
+        assert!(bytes("\\x1").is_err());
+        assert!(bytes("\\q").is_err());
+        assert!(matches!(
+            "^\\d{4}-".parse(),
+            Ok(RecordDelimiter::RecordStart(_))
+        ));
+        assert!("^(".parse::<RecordDelimiter>().is_err());
+    }
+
+    #[test]
+    fn test_command_record_decode() {
+        let fence = CommandRecord::decode(b"fence", b"my-token").unwrap();
+        assert_eq!(fence.to_string(), "new fencing token \"my-token\"");
+        assert_eq!(
+            serde_json::to_string(&fence).unwrap(),
+            r#"{"command":"fence","token":"my-token"}"#
+        );
+
+        let trim = CommandRecord::decode(b"trim", &42u64.to_be_bytes()).unwrap();
+        assert_eq!(trim, CommandRecord::Trim { trim_point: 42 });
+        assert_eq!(
+            serde_json::to_string(&trim).unwrap(),
+            r#"{"command":"trim","trim_point":42}"#
+        );
+
+        assert_eq!(CommandRecord::decode(b"other", b""), None);
+    }
+
+    #[tokio::test]
+    async fn test_split_stream() {
+        let input: &[u8] = b"a\0\0bc\0multi\nline\0";
+        let records: Vec<String> = split_stream(input, b"\0".to_vec())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records, ["a", "", "bc", "multi\nline"]);
+
+        let input: &[u8] = b"one--two--three";
+        let records: Vec<String> = split_stream(input, b"--".to_vec())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records, ["one", "two", "three"]);
+
+        // A delimiter split across reads.
+        let input = tokio::io::AsyncReadExt::chain(&b"one-"[..], &b"-two--"[..]);
+        let records: Vec<String> = split_stream(input, b"--".to_vec())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(records, ["one", "two"]);
+
+        let input: &[u8] = b"\xff\0";
+        let results: Vec<_> = split_stream(input, b"\0".to_vec()).collect().await;
+        assert!(results[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reader_at_offsets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("input");
+        std::fs::write(&path, "skip\na\r\nbb\nccc").unwrap();
+        let input = RecordsIn::File(path);
+
+        let (mut lines, offset) = input
+            .reader_at(&RecordDelimiter::default(), 5)
+            .await
+            .unwrap();
+        let mut seen = Vec::new();
+        while let Some(line) = lines.next().await {
+            seen.push((line.unwrap(), offset.get()));
+        }
+        assert_eq!(
+            seen,
+            [
+                ("a".to_owned(), 8),
+                ("bb".to_owned(), 11),
+                ("ccc".to_owned(), 14)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_lines_stream() {
+        let lines = [
+            "preamble",
+            "2024-01-01 error",
+            "  at foo",
+            "  at bar",
+            "2024-01-02 ok",
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, l)| Ok((l.to_owned(), i)));
+        let pattern = regex::Regex::new("^\\d{4}-").unwrap();
+        let records: Vec<(String, usize)> = group_lines_stream(stream::iter(lines), pattern)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            records,
+            [
+                ("preamble".to_owned(), 0),
+                ("2024-01-01 error\n  at foo\n  at bar".to_owned(), 3),
+                ("2024-01-02 ok".to_owned(), 4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! HTTP gateway to streams, for tools that cannot use an S2 SDK.
+//!
+//! `POST /basins/{basin}/streams/{stream}` appends newline-delimited JSON
+//! records, and `GET` on the same path streams records as Server-Sent Events.
+//! Requests are made with the CLI's credentials, so the gateway only accepts
+//! requests addressed to its listen address, requires a JSON content type for
+//! appends so that browsers must send a preflight request first, and can
+//! require a bearer token.
+
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use colored::Colorize;
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use s2_sdk::{
+    S2,
+    types::{
+        AppendRecord, FencingToken, ReadFrom, ReadInput, ReadLimits, ReadStart, ReadStop, S2Error,
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    cli::ServeArgs,
+    error::{CliError, RecordParseError},
+    ops,
+    record_format::{JsonBase64Formatter, JsonFormatter, RecordParser, RecordWriter},
+    types::{AppendAck, S2BasinAndStreamUri},
+};
+
+/// How long to wait for more records before flushing a batch, as for `append`.
+const APPEND_LINGER: Duration = Duration::from_millis(5);
+
+/// Record encoding, as for `--format` of `append` and `read`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum JsonFormat {
+    #[default]
+    Json,
+    JsonBase64,
+}
+
+#[derive(Debug, Deserialize)]
+struct AppendQuery {
+    #[serde(default)]
+    format: JsonFormat,
+    fencing_token: Option<String>,
+    match_seq_num: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadQuery {
+    #[serde(default)]
+    format: JsonFormat,
+    seq_num: Option<u64>,
+    timestamp: Option<u64>,
+    tail_offset: Option<u64>,
+    count: Option<usize>,
+}
+
+/// Error response, with a JSON body of the form `{"error": "..."}`.
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(msg: impl ToString) -> Self {
+        Self(StatusCode::BAD_REQUEST, msg.to_string())
+    }
+}
+
+/// Content types accepted for appends. Unlike `text/plain`, browsers cannot
+/// send these cross-origin without a preflight request.
+const APPEND_CONTENT_TYPES: [&str; 2] = ["application/json", "application/x-ndjson"];
+
+fn is_append_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            APPEND_CONTENT_TYPES
+                .iter()
+                .any(|ty| mime.trim().eq_ignore_ascii_case(ty))
+        })
+}
+
+/// Whether a `Host` header names the address the gateway listens on, so that
+/// DNS rebinding cannot point another name at it.
+///
+/// Any host is accepted when listening on an unspecified address, which
+/// requires a bearer token.
+fn is_allowed_host(host: &str, addr: SocketAddr) -> bool {
+    if addr.ip().is_unspecified() {
+        return true;
+    }
+    let Ok(authority) = host.parse::<http::uri::Authority>() else {
+        return false;
+    };
+    if authority.port_u16().unwrap_or(80) != addr.port() {
+        return false;
+    }
+    let name = authority.host();
+    if name.eq_ignore_ascii_case("localhost") {
+        return addr.ip().is_loopback();
+    }
+    name.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .is_ok_and(|ip: std::net::IpAddr| ip == addr.ip())
+}
+
+impl From<CliError> for ApiError {
+    fn from(e: CliError) -> Self {
+        let status = match &e {
+            CliError::Operation(_, S2Error::Validation(_)) => StatusCode::BAD_REQUEST,
+            CliError::Operation(_, S2Error::AppendConditionFailed(_)) => {
+                StatusCode::PRECONDITION_FAILED
+            }
+            CliError::Operation(_, S2Error::ReadUnwritten(_)) => StatusCode::RANGE_NOT_SATISFIABLE,
+            CliError::Operation(_, S2Error::Server(response))
+                if response.code.ends_with("_not_found") =>
+            {
+                StatusCode::NOT_FOUND
+            }
+            CliError::Operation(..) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn stream_uri(basin: &str, stream: &str) -> Result<S2BasinAndStreamUri, ApiError> {
+    format!("s2://{basin}/{stream}")
+        .parse()
+        .map_err(ApiError::bad_request)
+}
+
+/// Parse newline-delimited JSON records, skipping blank lines.
+async fn parse_records(body: &str, format: JsonFormat) -> Result<Vec<AppendRecord>, ApiError> {
+    let lines = stream::iter(
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(line.to_owned()))
+            .collect::<Vec<_>>(),
+    );
+    let records: Result<Vec<_>, RecordParseError> = match format {
+        JsonFormat::Json => JsonFormatter::parse_records(lines).try_collect().await,
+        JsonFormat::JsonBase64 => {
+            JsonBase64Formatter::parse_records(lines)
+                .try_collect()
+                .await
+        }
+    };
+    let records = records.map_err(ApiError::bad_request)?;
+    if records.is_empty() {
+        return Err(ApiError::bad_request("no records to append"));
+    }
+    Ok(records)
+}
+
+async fn append(
+    State(s2): State<S2>,
+    Path((basin, stream)): Path<(String, String)>,
+    Query(query): Query<AppendQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<AppendAck>, ApiError> {
+    if !is_append_content_type(&headers) {
+        return Err(ApiError(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!(
+                "content type must be one of {}",
+                APPEND_CONTENT_TYPES.join(", ")
+            ),
+        ));
+    }
+    let uri = stream_uri(&basin, &stream)?;
+    let fencing_token = query
+        .fencing_token
+        .map(|token| token.parse::<FencingToken>())
+        .transpose()
+        .map_err(ApiError::bad_request)?;
+    // Parse everything up front, so that invalid input appends nothing.
+    let records = parse_records(&body, query.format).await?;
+
+    let acks: Vec<_> = ops::append(
+        &s2,
+        stream::iter(records.into_iter().map(Ok::<_, Infallible>)),
+        uri,
+        fencing_token,
+        query.match_seq_num,
+        APPEND_LINGER,
+    )
+    .try_collect()
+    .await?;
+    let (first, last) = (&acks[0].batch, &acks[acks.len() - 1].batch);
+    Ok(Json(AppendAck {
+        start: first.start.into(),
+        end: last.end.into(),
+        tail: last.tail.into(),
+    }))
+}
+
+/// The read session for `query`, and how many of its records to stream.
+///
+/// Without a start position, reading follows new records from the tail. A count
+/// limit on the session would stop it at the tail at once, so it is instead
+/// applied to the records streamed.
+fn read_input(
+    query: &ReadQuery,
+    last_event_id: Option<u64>,
+) -> Result<(ReadInput, Option<usize>), ApiError> {
+    let starts = [query.seq_num, query.timestamp, query.tail_offset];
+    if starts.iter().flatten().count() > 1 {
+        return Err(ApiError::bad_request(
+            "only one of seq_num, timestamp and tail_offset can be given",
+        ));
+    }
+    let follow = starts.iter().all(Option::is_none);
+    let from = match last_event_id {
+        Some(seq_num) => ReadFrom::SeqNum(seq_num + 1),
+        None => ops::read_from(query.seq_num, query.timestamp, query.tail_offset, None)
+            .unwrap_or(ReadFrom::TailOffset(0)),
+    };
+    let input = ReadInput::new().with_start(ReadStart::new().with_from(from));
+    Ok(match query.count {
+        Some(count) if follow => (input, Some(count)),
+        Some(count) => (
+            input.with_stop(ReadStop::new().with_limits(ReadLimits::new().with_count(count))),
+            None,
+        ),
+        None => (input, None),
+    })
+}
+
+async fn read(
+    State(s2): State<S2>,
+    Path((basin, stream)): Path<(String, String)>,
+    Query(query): Query<ReadQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let uri = stream_uri(&basin, &stream)?;
+    // `EventSource` sends the ID of the last event it received when reconnecting.
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|id| id.to_str().ok()?.parse::<u64>().ok());
+    let (input, count) = read_input(&query, last_event_id)?;
+    let batches = ops::read_session(&s2, uri, input).await?;
+
+    let events = async_stream::stream! {
+        let records = ops::records(batches).take(count.unwrap_or(usize::MAX));
+        let mut records = std::pin::pin!(records);
+        while let Some(record) = records.next().await {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    yield Ok(Event::default().event("error").data(e.to_string()));
+                    break;
+                }
+            };
+            let mut data = Vec::new();
+            let written = match query.format {
+                JsonFormat::Json => JsonFormatter {}.write_record(&record, &mut data).await,
+                JsonFormat::JsonBase64 => {
+                    JsonBase64Formatter {}.write_record(&record, &mut data).await
+                }
+            };
+            if let Err(e) = written {
+                yield Ok(Event::default().event("error").data(e.to_string()));
+                break;
+            }
+            yield Ok(Event::default()
+                .id(record.seq_num.to_string())
+                .data(String::from_utf8_lossy(&data)));
+        }
+    };
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Allow cross-origin requests from `origin`, answering preflight requests directly.
+async fn cors(State(origin): State<HeaderValue>, request: Request, next: Next) -> Response {
+    let mut response = if request.method() == Method::OPTIONS {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        next.run(request).await
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("authorization, content-type, last-event-id"),
+    );
+    response
+}
+
+/// Reject requests whose `Host` header does not name the listen address.
+async fn check_host(State(addr): State<SocketAddr>, request: Request, next: Next) -> Response {
+    let allowed = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .is_some_and(|host| is_allowed_host(host, addr));
+    if !allowed {
+        return ApiError(
+            StatusCode::MISDIRECTED_REQUEST,
+            "unexpected host".to_owned(),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Require `Authorization: Bearer <token>`, except for CORS preflight requests.
+async fn check_token(State(token): State<String>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok()?.strip_prefix("Bearer "))
+        .is_some_and(|given| given == token);
+    if !authorized && request.method() != Method::OPTIONS {
+        return ApiError(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid token".to_owned(),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+pub async fn run(s2: S2, args: ServeArgs) -> Result<(), CliError> {
+    if !args.listen.ip().is_loopback() && args.token.is_none() {
+        return Err(CliError::InvalidArgs(miette::miette!(
+            "Refusing to listen on non-loopback address {} without --token",
+            args.listen
+        )));
+    }
+
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .map_err(|e| CliError::Serve(format!("failed to listen on {}: {e}", args.listen)))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| CliError::Serve(e.to_string()))?;
+
+    let mut app = Router::new()
+        .route("/basins/{basin}/streams/{*stream}", get(read).post(append))
+        .with_state(s2);
+    if let Some(token) = args.token {
+        app = app.layer(middleware::from_fn_with_state(token, check_token));
+    }
+    if let Some(origin) = args.allow_origin {
+        app = app.layer(middleware::from_fn_with_state(origin, cors));
+    }
+    let app = app.layer(middleware::from_fn_with_state(addr, check_host));
+
+    eprintln!("{}", format!("⦿ Serving on http://{addr}").blue().bold());
+
+    // Read sessions may never end, so stop without waiting for connections to close.
+    tokio::select! {
+        result = axum::serve(listener, app) => result.map_err(|e| CliError::Serve(e.to_string())),
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("{}", "■ [STOPPED]".red().bold());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+    use s2_sdk::types::{AppendRecord, Header};
+
+    use super::{
+        JsonFormat, ReadQuery, is_allowed_host, is_append_content_type, parse_records, read_input,
+        stream_uri,
+    };
+
+    #[tokio::test]
+    async fn test_parse_records() {
+        let body = "{\"body\":\"a\",\"headers\":[[\"k\",\"v\"]]}\n\n{\"body\":\"b\"}\n";
+        let records = parse_records(body, JsonFormat::Json).await.unwrap();
+        assert_eq!(
+            records,
+            vec![
+                AppendRecord::new("a")
+                    .unwrap()
+                    .with_headers([Header::new("k", "v")])
+                    .unwrap(),
+                AppendRecord::new("b").unwrap(),
+            ]
+        );
+
+        let records = parse_records("{\"body\":\"YQ==\"}", JsonFormat::JsonBase64)
+            .await
+            .unwrap();
+        assert_eq!(records, vec![AppendRecord::new("a").unwrap()]);
+
+        for body in ["", "\n", "not json"] {
+            let e = parse_records(body, JsonFormat::Json).await.unwrap_err();
+            assert_eq!(e.0, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_read_input_count() {
+        let query = |seq_num: Option<u64>, tail_offset: Option<u64>| ReadQuery {
+            format: JsonFormat::default(),
+            seq_num,
+            timestamp: None,
+            tail_offset,
+            count: Some(5),
+        };
+
+        // Following new records, the session must not stop at the tail.
+        let (input, count) = read_input(&query(None, None), None).unwrap();
+        assert_eq!(input.stop.limits.count, None);
+        assert_eq!(count, Some(5));
+        let (input, count) = read_input(&query(None, None), Some(41)).unwrap();
+        assert_eq!(input.stop.limits.count, None);
+        assert_eq!(count, Some(5));
+
+        let (input, count) = read_input(&query(Some(10), None), None).unwrap();
+        assert_eq!(input.stop.limits.count, Some(5));
+        assert_eq!(count, None);
+
+        assert!(read_input(&query(Some(10), Some(1)), None).is_err());
+    }
+
+    #[test]
+    fn test_stream_uri() {
+        let uri = stream_uri("my-basin-1", "events/2024").unwrap();
+        assert_eq!(uri.to_string(), "s2://my-basin-1/events/2024");
+        assert!(stream_uri("b", "events").is_err());
+    }
+
+    #[test]
+    fn test_is_append_content_type() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_append_content_type(&headers));
+        for (ty, ok) in [
+            ("application/json", true),
+            ("application/x-ndjson", true),
+            ("Application/JSON; charset=utf-8", true),
+            ("text/plain", false),
+            ("application/x-www-form-urlencoded", false),
+        ] {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(ty));
+            assert_eq!(is_append_content_type(&headers), ok, "{ty}");
+        }
+    }
+
+    #[test]
+    fn test_is_allowed_host() {
+        let loopback = "127.0.0.1:8787".parse().unwrap();
+        assert!(is_allowed_host("127.0.0.1:8787", loopback));
+        assert!(is_allowed_host("localhost:8787", loopback));
+        assert!(!is_allowed_host("localhost:8788", loopback));
+        assert!(!is_allowed_host("attacker.example:8787", loopback));
+        assert!(!is_allowed_host("127.0.0.1", loopback));
+
+        let ipv6 = "[::1]:80".parse().unwrap();
+        assert!(is_allowed_host("[::1]", ipv6));
+        assert!(is_allowed_host("localhost", ipv6));
+
+        let public = "10.0.0.5:8787".parse().unwrap();
+        assert!(is_allowed_host("10.0.0.5:8787", public));
+        assert!(!is_allowed_host("localhost:8787", public));
+
+        assert!(is_allowed_host(
+            "any.example:1",
+            "0.0.0.0:8787".parse().unwrap()
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+//! Fanning out appends across sharded streams.
+//!
+//! Each record is routed by a stable hash of its key to one of the streams
+//! named by a `{N..M}` or `{a,b,...}` pattern, so records with the same key
+//! always land on the same stream, in input order.
+
+use std::{convert::Infallible, str::FromStr, time::Duration};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use s2_sdk::{
+    S2,
+    producer::IndexedAppendAck,
+    types::{AppendRecord, FencingToken, StreamName},
+};
+use tokio::{select, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    error::{CliError, RecordParseError},
+    ops,
+    record_format::ParsedRecord,
+    types::S2BasinAndStreamUri,
+};
+
+/// Records buffered per shard while its producer is busy.
+const SHARD_BUFFER: usize = 128;
+
+/// Key of a record that determines its shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardKey {
+    /// Value of the first header with this name.
+    Header(String),
+    /// Field of a JSON body, as a path of nested object keys.
+    Body(Vec<String>),
+}
+
+impl FromStr for ShardKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("header", name)) if !name.is_empty() => Ok(Self::Header(name.to_owned())),
+            Some(("body", path)) if !path.is_empty() => {
+                Ok(Self::Body(path.split('.').map(str::to_owned).collect()))
+            }
+            _ => Err(format!(
+                "expected \"header:NAME\" or \"body:FIELD\", found {s:?}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ShardKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Header(name) => write!(f, "header:{name}"),
+            Self::Body(path) => write!(f, "body:{}", path.join(".")),
+        }
+    }
+}
+
+impl ShardKey {
+    /// The key of `record`, if present.
+    ///
+    /// String fields are keyed by their contents and other JSON values by their
+    /// serialization; a null field counts as absent.
+    fn extract(&self, record: &ParsedRecord) -> Option<Bytes> {
+        match self {
+            Self::Header(name) => record
+                .headers
+                .iter()
+                .find(|h| h.name == name.as_bytes())
+                .map(|h| h.value.clone()),
+            Self::Body(path) => {
+                let value: serde_json::Value = serde_json::from_slice(&record.body).ok()?;
+                match path.iter().try_fold(&value, |value, key| value.get(key))? {
+                    serde_json::Value::Null => None,
+                    serde_json::Value::String(s) => Some(Bytes::copy_from_slice(s.as_bytes())),
+                    other => Some(other.to_string().into()),
+                }
+            }
+        }
+    }
+
+    /// Index of the shard for `record`, out of `shards`.
+    fn shard(&self, record: &ParsedRecord, shards: usize) -> Result<usize, RecordParseError> {
+        let key = self
+            .extract(record)
+            .ok_or_else(|| RecordParseError::Parse(format!("record has no {self} shard key")))?;
+        Ok(shard_index(&key, shards))
+    }
+}
+
+fn shard_index(key: &[u8], shards: usize) -> usize {
+    (xxh3_64(key) % shards as u64) as usize
+}
+
+/// Expand the `{N..M}` (inclusive) or `{a,b,...}` pattern in the stream name
+/// of `uri` into the shard streams.
+///
+/// Ranges are zero-padded to the width of their bounds if either has a leading zero.
+pub fn expand(uri: &S2BasinAndStreamUri) -> Result<Vec<S2BasinAndStreamUri>, String> {
+    let name = uri.stream.as_ref();
+    let (open, close) = match (name.find('{'), name.rfind('}')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => {
+            return Err(format!(
+                "{uri} must name shards with a {{N..M}} or {{a,b,...}} pattern"
+            ));
+        }
+    };
+    let (prefix, pattern, suffix) = (&name[..open], &name[open + 1..close], &name[close + 1..]);
+
+    let parts: Vec<String> = if let Some((start, end)) = pattern.split_once("..") {
+        let bound = |s: &str| {
+            s.parse::<u64>()
+                .map_err(|_| format!("invalid shard range {{{pattern}}}"))
+        };
+        let (lo, hi) = (bound(start)?, bound(end)?);
+        if lo > hi {
+            return Err(format!("empty shard range {{{pattern}}}"));
+        }
+        let padded = [start, end]
+            .iter()
+            .any(|s| s.len() > 1 && s.starts_with('0'));
+        let width = if padded {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        (lo..=hi).map(|n| format!("{n:0width$}")).collect()
+    } else {
+        pattern.split(',').map(str::to_owned).collect()
+    };
+
+    let mut shards = Vec::with_capacity(parts.len());
+    for part in parts {
+        let stream: StreamName = format!("{prefix}{part}{suffix}")
+            .parse()
+            .map_err(|e| format!("invalid shard stream name: {e}"))?;
+        let shard = S2BasinAndStreamUri {
+            basin: uri.basin.clone(),
+            stream,
+        };
+        if shards.contains(&shard) {
+            return Err(format!("duplicate shard {shard}"));
+        }
+        shards.push(shard);
+    }
+    Ok(shards)
+}
+
+/// Append each record to the shard picked by its key, running one producer per shard.
+///
+/// Acks are tagged with the index of their shard in `shards`.
+pub fn append<'a, S>(
+    s2: &'a S2,
+    records: S,
+    shards: &[S2BasinAndStreamUri],
+    key: ShardKey,
+    fencing_token: Option<FencingToken>,
+    linger: Duration,
+) -> impl Stream<Item = Result<(usize, IndexedAppendAck), CliError>> + Send + 'a
+where
+    S: Stream<Item = Result<ParsedRecord, RecordParseError>> + Send + Unpin + 'a,
+{
+    let (senders, acks): (Vec<_>, Vec<_>) = shards
+        .iter()
+        .enumerate()
+        .map(|(i, uri)| {
+            let (tx, rx) = mpsc::channel::<AppendRecord>(SHARD_BUFFER);
+            let acks = ops::append(
+                s2,
+                ReceiverStream::new(rx).map(Ok::<_, Infallible>),
+                uri.clone(),
+                fencing_token.clone(),
+                None,
+                linger,
+            )
+            .map_ok(move |ack| (i, ack));
+            (tx, acks.boxed())
+        })
+        .unzip();
+    let mut acks = stream::select_all(acks);
+
+    // Dropping the senders once the input ends lets each producer finish.
+    let route = async move {
+        let mut records = records;
+        while let Some(record) = records.next().await {
+            let record = record?;
+            let shard = key.shard(&record, senders.len())?;
+            if senders[shard].send(record.try_into()?).await.is_err() {
+                // The shard's producer failed, and reports why through its acks.
+                break;
+            }
+        }
+        Ok::<_, CliError>(())
+    };
+
+    async_stream::try_stream! {
+        let mut route = std::pin::pin!(route);
+        let mut routed = false;
+        loop {
+            let ack = select! {
+                result = &mut route, if !routed => {
+                    routed = true;
+                    result.map(|()| None)
+                }
+                ack = acks.next() => match ack {
+                    Some(ack) => ack.map(Some),
+                    None => break,
+                },
+            };
+            if let Some(ack) = ack? {
+                yield ack;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use s2_sdk::types::Header;
+
+    use super::{ShardKey, expand, shard_index};
+    use crate::{record_format::ParsedRecord, types::S2BasinAndStreamUri};
+
+    fn streams(uri: &str) -> Result<Vec<String>, String> {
+        let uri: S2BasinAndStreamUri = uri.parse().unwrap();
+        Ok(expand(&uri)?
+            .into_iter()
+            .map(|shard| shard.stream.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            streams("s2://my-basin/events/{0..3}").unwrap(),
+            vec!["events/0", "events/1", "events/2", "events/3"]
+        );
+        assert_eq!(
+            streams("s2://my-basin/events/{08..10}/log").unwrap(),
+            vec!["events/08/log", "events/09/log", "events/10/log"]
+        );
+        assert_eq!(
+            streams("s2://my-basin/{us,eu}-events").unwrap(),
+            vec!["us-events", "eu-events"]
+        );
+        assert!(streams("s2://my-basin/events").is_err());
+        assert!(streams("s2://my-basin/events/{3..1}").is_err());
+        assert!(streams("s2://my-basin/events/{a..b}").is_err());
+        assert!(streams("s2://my-basin/events/{a,a}").is_err());
+    }
+
+    #[test]
+    fn test_shard_key() {
+        assert_eq!(
+            "header:tenant".parse(),
+            Ok(ShardKey::Header("tenant".to_owned()))
+        );
+        assert_eq!(
+            "body:user.id".parse(),
+            Ok(ShardKey::Body(vec!["user".to_owned(), "id".to_owned()]))
+        );
+        assert!("header:".parse::<ShardKey>().is_err());
+        assert!("tenant".parse::<ShardKey>().is_err());
+
+        let record = ParsedRecord {
+            headers: vec![Header::new("tenant", "acme")],
+            ..ParsedRecord::new(r#"{"user":{"id":42,"name":"ada"},"org":null}"#)
+        };
+        let key = |key: &str| key.parse::<ShardKey>().unwrap().extract(&record);
+        assert_eq!(key("header:tenant").as_deref(), Some(&b"acme"[..]));
+        assert_eq!(key("body:user.id").as_deref(), Some(&b"42"[..]));
+        assert_eq!(key("body:user.name").as_deref(), Some(&b"ada"[..]));
+        assert_eq!(key("header:region"), None);
+        assert_eq!(key("body:org"), None);
+        assert_eq!(key("body:user.email"), None);
+    }
+
+    #[test]
+    fn test_shard_index_is_stable() {
+        // Changing the hash would move existing keys to different shards.
+        assert_eq!(shard_index(b"acme", 16), 1);
+        assert!((0..100).all(|i| shard_index(format!("key-{i}").as_bytes(), 16) < 16));
+    }
+}
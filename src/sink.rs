@@ -0,0 +1,232 @@
+use colored::Colorize;
+use futures::{Stream, StreamExt};
+use s2_sdk::types::SequencedRecord;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    select,
+};
+
+use crate::{
+    cli::{CommandRecordArgs, ReadArgs, RecordFilterArgs, TailArgs},
+    error::CliError,
+    filter::RecordFilter,
+    format_position,
+    record_format::{
+        BinaryFormatter, CommandRecord, CsvColumn, CsvFormatter, JsonBase64Formatter,
+        JsonFormatter, RecordFormat, RecordWriter, TemplateFormatter, TextFormatter,
+    },
+    types::S2BasinAndStreamUri,
+};
+
+fn write_error(e: impl ToString) -> CliError {
+    CliError::RecordWrite(e.to_string())
+}
+
+/// Filters, formats and writes the records of `read` and `tail`.
+pub struct RecordSink<W = Box<dyn AsyncWrite + Send + Unpin>> {
+    writer: W,
+    format: RecordFormat,
+    csv: CsvFormatter,
+    template: Option<TemplateFormatter>,
+    separator: Vec<u8>,
+    only_commands: bool,
+    filter: Option<RecordFilter>,
+}
+
+impl RecordSink {
+    pub async fn for_read(args: &ReadArgs) -> Result<Self, CliError> {
+        Self::new(
+            args.output.writer().await.map_err(write_error)?,
+            args.format,
+            args.csv_columns.clone(),
+            args.template.clone(),
+            args.delimiter.separator(),
+            &args.commands,
+            &args.filter,
+        )
+        .await
+    }
+
+    pub async fn for_tail(args: &TailArgs) -> Result<Self, CliError> {
+        Self::new(
+            args.output.writer().await.map_err(write_error)?,
+            args.format,
+            args.csv_columns.clone(),
+            args.template.clone(),
+            b"\n",
+            &args.commands,
+            &args.filter,
+        )
+        .await
+    }
+}
+
+impl RecordSink<Vec<u8>> {
+    /// A sink for the records of `read` that buffers their output, starting
+    /// with the preamble, to be taken with [`Self::take`].
+    pub async fn buffered(args: &ReadArgs) -> Result<Self, CliError> {
+        Self::new(
+            Vec::new(),
+            args.format,
+            args.csv_columns.clone(),
+            args.template.clone(),
+            args.delimiter.separator(),
+            &args.commands,
+            &args.filter,
+        )
+        .await
+    }
+
+    /// Take the output buffered so far.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.writer)
+    }
+}
+
+impl<W: AsyncWrite + Send + Unpin> RecordSink<W> {
+    async fn new(
+        writer: W,
+        format: RecordFormat,
+        csv_columns: Vec<CsvColumn>,
+        template: Option<TemplateFormatter>,
+        separator: &[u8],
+        commands: &CommandRecordArgs,
+        filter: &RecordFilterArgs,
+    ) -> Result<Self, CliError> {
+        let mut sink = Self {
+            writer,
+            format,
+            csv: CsvFormatter::new(csv_columns),
+            template,
+            separator: separator.to_vec(),
+            only_commands: commands.only_commands,
+            filter: RecordFilter::new(filter),
+        };
+        sink.write_preamble().await?;
+        Ok(sink)
+    }
+
+    async fn write_preamble(&mut self) -> Result<(), CliError> {
+        if let RecordFormat::Csv = self.format {
+            self.csv
+                .write_preamble(&mut self.writer)
+                .await
+                .map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Write a record unless it is filtered out, returning whether it was written.
+    ///
+    /// In JSON formats, the record is annotated with `stream` if provided.
+    pub async fn write(
+        &mut self,
+        record: &SequencedRecord,
+        stream: Option<&S2BasinAndStreamUri>,
+    ) -> Result<bool, CliError> {
+        if self.only_commands && !record.is_command_record() {
+            return Ok(false);
+        }
+        if self.filter.as_mut().is_some_and(|f| !f.matches(record)) {
+            return Ok(false);
+        }
+        self.write_record(record, stream).await?;
+        // Binary frames are self-delimiting.
+        let skip_separator = match self.format {
+            RecordFormat::Text => self.template.is_none() && record.is_command_record(),
+            RecordFormat::Binary => true,
+            _ => false,
+        };
+        if !skip_separator {
+            self.writer
+                .write_all(&self.separator)
+                .await
+                .map_err(write_error)?;
+        }
+        Ok(true)
+    }
+
+    async fn write_record(
+        &mut self,
+        record: &SequencedRecord,
+        stream: Option<&S2BasinAndStreamUri>,
+    ) -> Result<(), CliError> {
+        let writer = &mut self.writer;
+        if let Some(template) = &self.template {
+            return template
+                .write_record(record, writer)
+                .await
+                .map_err(write_error);
+        }
+        let stream = stream.map(|uri| uri.to_string());
+        match (self.format, stream) {
+            (RecordFormat::Text, _) => {
+                if record.is_command_record() {
+                    let cmd_desc = CommandRecord::from_record(record)
+                        .map_or_else(|| "unknown command".to_owned(), |cmd| cmd.to_string());
+                    eprintln!(
+                        "{} // {}",
+                        cmd_desc.bold(),
+                        format_position(record.seq_num, record.timestamp)
+                    );
+                    Ok(())
+                } else {
+                    TextFormatter.write_record(record, writer).await
+                }
+            }
+            (RecordFormat::Json, Some(stream)) => {
+                JsonFormatter {}
+                    .write_sourced_record(record, &stream, writer)
+                    .await
+            }
+            (RecordFormat::Json, None) => JsonFormatter {}.write_record(record, writer).await,
+            (RecordFormat::JsonBase64, Some(stream)) => {
+                JsonBase64Formatter {}
+                    .write_sourced_record(record, &stream, writer)
+                    .await
+            }
+            (RecordFormat::JsonBase64, None) => {
+                JsonBase64Formatter {}.write_record(record, writer).await
+            }
+            (RecordFormat::Csv, _) => self.csv.write_record(record, writer).await,
+            (RecordFormat::Binary, _) => BinaryFormatter.write_record(record, writer).await,
+        }
+        .map_err(write_error)
+    }
+
+    pub async fn flush(&mut self) -> Result<(), CliError> {
+        self.writer.flush().await.map_err(write_error)
+    }
+
+    /// Write records as they arrive, until they end or the user interrupts.
+    pub async fn write_all<S>(&mut self, records: S) -> Result<(), CliError>
+    where
+        S: Stream<Item = Result<(Option<S2BasinAndStreamUri>, SequencedRecord), CliError>>,
+    {
+        let mut records = std::pin::pin!(records);
+        loop {
+            select! {
+                next = records.next() => match next {
+                    Some(Ok((stream, record))) => {
+                        self.write(&record, stream.as_ref()).await?;
+                        self.flush().await?;
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => {
+                    eprintln!("{}", "■ [ABORTED]".red().bold());
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Report how many records matched the filters, if any.
+    pub fn report(&self) {
+        if let Some(filter) = &self.filter {
+            filter.report();
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bytes::Bytes;
+use colored::Colorize;
+use futures::{StreamExt, TryStreamExt, future};
+use s2_sdk::{
+    S2,
+    types::{MeteredBytes, ReadFrom, ReadInput, ReadLimits, ReadStart, ReadStop, S2Error},
+};
+use serde::Serialize;
+use tokio::select;
+
+use crate::{
+    cli::StatsArgs,
+    error::CliError,
+    ops,
+    types::{S2BasinAndStreamUri, serialize_display},
+};
+
+/// Upper bounds of the record size histogram buckets, in bytes.
+/// The last bucket covers the maximum record size of 1 MiB.
+const SIZE_BUCKETS: [u64; 8] = [
+    64,
+    256,
+    1024,
+    4 * 1024,
+    16 * 1024,
+    64 * 1024,
+    256 * 1024,
+    1024 * 1024,
+];
+
+/// Distinct values tracked per header name, beyond which the count is a lower bound.
+const MAX_DISTINCT_VALUES: usize = 10_000;
+
+/// Leading bits of a value kept by [`Histogram`] buckets, so that percentiles
+/// are within 1/128 of the true value.
+const HISTOGRAM_BITS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Position {
+    pub seq_num: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Distribution {
+    pub min: u64,
+    pub median: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl Distribution {
+    fn compute(histogram: &Histogram) -> Option<Self> {
+        Some(Self {
+            min: histogram.min?,
+            median: histogram.percentile(0.5)?,
+            p90: histogram.percentile(0.90)?,
+            p99: histogram.percentile(0.99)?,
+            max: histogram.max?,
+        })
+    }
+}
+
+/// Log-linear histogram, whose memory is bounded however many values it records.
+///
+/// Values are bucketed by their leading [`HISTOGRAM_BITS`] bits, so smaller
+/// values are exact and larger ones are rounded down by less than 1/128.
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Values recorded, by the lowest value of their bucket.
+    buckets: BTreeMap<u64, u64>,
+    count: u64,
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl Histogram {
+    fn record(&mut self, value: u64) {
+        let shift = (u64::BITS - value.leading_zeros()).saturating_sub(HISTOGRAM_BITS);
+        *self.buckets.entry(value >> shift << shift).or_default() += 1;
+        self.count += 1;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// Nearest-rank percentile, for `p` in `(0, 1]`.
+    fn percentile(&self, p: f64) -> Option<u64> {
+        let rank = ((self.count as f64 * p).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        let (bucket, _) = self.buckets.iter().find(|(_, count)| {
+            seen += **count;
+            seen >= rank
+        })?;
+        Some((*bucket).clamp(self.min?, self.max?))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeBucket {
+    /// Inclusive upper bound of metered record size, in bytes.
+    pub up_to: u64,
+    pub records: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeaderStats {
+    pub name: String,
+    /// Records with at least one header of this name.
+    pub records: u64,
+    pub distinct_values: u64,
+    /// Whether more distinct values were seen than are tracked,
+    /// so that `distinct_values` is a lower bound.
+    pub distinct_values_truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimestampGaps {
+    /// Milliseconds between consecutive records.
+    pub gap_ms: Distribution,
+    /// Record following the largest gap.
+    pub max_gap_before: Position,
+    /// Records with a timestamp earlier than the preceding record.
+    pub out_of_order: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RateBucket {
+    /// Start of the bucket, in milliseconds since Unix epoch.
+    pub start: u64,
+    pub records: u64,
+    pub metered_bytes: u64,
+    pub records_per_sec: f64,
+}
+
+/// Summary of the records in a range of a stream.
+#[derive(Debug, Serialize)]
+pub struct StreamStats {
+    #[serde(serialize_with = "serialize_display")]
+    pub stream: S2BasinAndStreamUri,
+    pub records: u64,
+    pub command_records: u64,
+    pub metered_bytes: u64,
+    pub first: Option<Position>,
+    pub last: Option<Position>,
+    /// Metered record sizes, in bytes, with percentiles within 1%.
+    pub record_size: Option<Distribution>,
+    pub size_histogram: Vec<SizeBucket>,
+    pub headers: Vec<HeaderStats>,
+    pub timestamp_gaps: Option<TimestampGaps>,
+    /// Width of the rate buckets, in milliseconds.
+    pub interval_ms: u64,
+    /// Non-empty rate buckets, in order.
+    pub rate: Vec<RateBucket>,
+}
+
+#[derive(Default)]
+struct HeaderAcc {
+    records: u64,
+    values: HashSet<Bytes>,
+    truncated: bool,
+}
+
+pub async fn run(s2: &S2, args: StatsArgs) -> Result<StreamStats, CliError> {
+    let interval_ms = (args.interval.as_millis() as u64).max(1);
+    let mut stats = StreamStats {
+        stream: args.uri.clone(),
+        records: 0,
+        command_records: 0,
+        metered_bytes: 0,
+        first: None,
+        last: None,
+        record_size: None,
+        size_histogram: Vec::new(),
+        headers: Vec::new(),
+        timestamp_gaps: None,
+        interval_ms,
+        rate: Vec::new(),
+    };
+
+    let tail = ops::check_tail(s2, args.uri.clone()).await?.seq_num;
+    if tail == 0 {
+        return Ok(stats);
+    }
+
+    // Limits keep the session from waiting for new records at the tail.
+    let from = ops::read_from(args.seq_num, args.timestamp, args.tail_offset, args.ago)
+        .unwrap_or(ReadFrom::SeqNum(0));
+    let count = args.count.map_or(tail, |count| count.min(tail));
+    let mut stop = ReadStop::new().with_limits(ReadLimits::new().with_count(count as usize));
+    if let Some(until) = args.until {
+        stop = stop.with_until(..until);
+    }
+    let batches = match ops::read_session(
+        s2,
+        args.uri.clone(),
+        ReadInput::new()
+            .with_start(ReadStart::new().with_from(from))
+            .with_stop(stop),
+    )
+    .await
+    {
+        Ok(batches) => batches,
+        Err(CliError::Operation(_, S2Error::ReadUnwritten(_))) => return Ok(stats),
+        Err(e) => return Err(e),
+    };
+    let records =
+        ops::records(batches).try_take_while(|record| future::ready(Ok(record.seq_num < tail)));
+    let mut records = std::pin::pin!(records);
+
+    let mut sizes = Histogram::default();
+    let mut histogram = [0u64; SIZE_BUCKETS.len()];
+    let mut headers: BTreeMap<String, HeaderAcc> = BTreeMap::new();
+    let mut gaps = Histogram::default();
+    let mut max_gap: Option<(u64, Position)> = None;
+    let mut out_of_order = 0;
+    let mut rate: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+
+    loop {
+        select! {
+            record = records.next() => {
+                let record = match record {
+                    Some(Ok(record)) => record,
+                    Some(Err(CliError::Operation(_, S2Error::ReadUnwritten(_)))) | None => break,
+                    Some(Err(e)) => return Err(e),
+                };
+                let position = Position {
+                    seq_num: record.seq_num,
+                    timestamp: record.timestamp,
+                };
+                let size = record.metered_bytes() as u64;
+                stats.records += 1;
+                stats.metered_bytes += size;
+                if record.is_command_record() {
+                    stats.command_records += 1;
+                }
+
+                sizes.record(size);
+                let bucket = SIZE_BUCKETS
+                    .iter()
+                    .position(|up_to| size <= *up_to)
+                    .unwrap_or(SIZE_BUCKETS.len() - 1);
+                histogram[bucket] += 1;
+
+                let mut seen = HashSet::new();
+                for header in &record.headers {
+                    let name = String::from_utf8_lossy(&header.name).into_owned();
+                    let acc = headers.entry(name.clone()).or_default();
+                    if seen.insert(name) {
+                        acc.records += 1;
+                    }
+                    if acc.values.len() < MAX_DISTINCT_VALUES {
+                        acc.values.insert(header.value.clone());
+                    } else if !acc.values.contains(&header.value) {
+                        acc.truncated = true;
+                    }
+                }
+
+                if let Some(last) = stats.last {
+                    if record.timestamp < last.timestamp {
+                        out_of_order += 1;
+                    }
+                    let gap = record.timestamp.saturating_sub(last.timestamp);
+                    gaps.record(gap);
+                    if max_gap.is_none_or(|(max, _)| gap > max) {
+                        max_gap = Some((gap, position));
+                    }
+                }
+
+                let first = *stats.first.get_or_insert(position);
+                let bucket = rate
+                    .entry(record.timestamp.saturating_sub(first.timestamp) / interval_ms)
+                    .or_default();
+                bucket.0 += 1;
+                bucket.1 += size;
+
+                stats.last = Some(position);
+            }
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED] reporting records read so far".red().bold());
+                break;
+            }
+        }
+    }
+
+    stats.record_size = Distribution::compute(&sizes);
+    stats.size_histogram = SIZE_BUCKETS
+        .iter()
+        .zip(histogram)
+        .map(|(up_to, records)| SizeBucket {
+            up_to: *up_to,
+            records,
+        })
+        .collect();
+    stats.headers = headers
+        .into_iter()
+        .map(|(name, acc)| HeaderStats {
+            name,
+            records: acc.records,
+            distinct_values: acc.values.len() as u64,
+            distinct_values_truncated: acc.truncated,
+        })
+        .collect();
+    stats.timestamp_gaps =
+        Distribution::compute(&gaps)
+            .zip(max_gap)
+            .map(|(gap_ms, (_, max_gap_before))| TimestampGaps {
+                gap_ms,
+                max_gap_before,
+                out_of_order,
+            });
+    if let Some(first) = stats.first {
+        stats.rate = rate
+            .into_iter()
+            .map(|(bucket, (records, metered_bytes))| RateBucket {
+                start: first.timestamp + bucket * interval_ms,
+                records,
+                metered_bytes,
+                records_per_sec: records as f64 * 1000.0 / interval_ms as f64,
+            })
+            .collect();
+    }
+
+    Ok(stats)
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    humantime::format_rfc3339_millis(
+        std::time::UNIX_EPOCH + std::time::Duration::from_millis(timestamp),
+    )
+    .to_string()
+}
+
+fn bar(value: u64, max: u64) -> String {
+    const MAX_BAR_LEN: u64 = 40;
+    let len = if max == 0 {
+        0
+    } else {
+        (value * MAX_BAR_LEN).div_ceil(max)
+    };
+    "⠸".repeat(len as usize)
+}
+
+/// Print stats in a human-friendly layout.
+pub fn print(stats: &StreamStats) {
+    let heading = |s: &str| println!("{}", s.yellow().bold());
+    let position = |p: &Option<Position>| match p {
+        Some(p) => format!("{} @ {}", p.seq_num, format_timestamp(p.timestamp)),
+        None => "-".to_owned(),
+    };
+
+    heading(&format!("Stats for {}", stats.stream));
+    println!(
+        "records:       {} ({} command records)",
+        stats.records.to_string().green().bold(),
+        stats.command_records
+    );
+    println!(
+        "metered bytes: {}",
+        stats.metered_bytes.to_string().green().bold()
+    );
+    println!("first:         {}", position(&stats.first));
+    println!("last:          {}", position(&stats.last));
+    if stats.records == 0 {
+        return;
+    }
+
+    if let Some(size) = &stats.record_size {
+        println!();
+        heading("Record size (bytes)");
+        println!(
+            "min {} | median {} | p90 {} | p99 {} | max {}",
+            size.min, size.median, size.p90, size.p99, size.max
+        );
+        let max = stats
+            .size_histogram
+            .iter()
+            .map(|b| b.records)
+            .max()
+            .unwrap_or(0);
+        for bucket in &stats.size_histogram {
+            println!(
+                "  <= {:>7} │ {:>8} {}",
+                bucket.up_to,
+                bucket.records,
+                bar(bucket.records, max)
+            );
+        }
+    }
+
+    if !stats.headers.is_empty() {
+        println!();
+        heading("Headers");
+        for header in &stats.headers {
+            println!(
+                "  {:20} {:>8} records, {}{} distinct values",
+                format!("{:?}", header.name),
+                header.records,
+                header.distinct_values,
+                if header.distinct_values_truncated {
+                    "+"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    if let Some(gaps) = &stats.timestamp_gaps {
+        println!();
+        heading("Timestamp gaps (ms)");
+        println!(
+            "min {} | median {} | p90 {} | p99 {} | max {} (before seq_num {})",
+            gaps.gap_ms.min,
+            gaps.gap_ms.median,
+            gaps.gap_ms.p90,
+            gaps.gap_ms.p99,
+            gaps.gap_ms.max,
+            gaps.max_gap_before.seq_num
+        );
+        if gaps.out_of_order > 0 {
+            println!("out of order:  {}", gaps.out_of_order.to_string().red());
+        }
+    }
+
+    println!();
+    heading(&format!(
+        "Records per second ({} buckets)",
+        humantime::format_duration(std::time::Duration::from_millis(stats.interval_ms))
+    ));
+    let max = stats.rate.iter().map(|b| b.records).max().unwrap_or(0);
+    for bucket in &stats.rate {
+        println!(
+            "  {} │ {:>10.2} {}",
+            format_timestamp(bucket.start),
+            bucket.records_per_sec,
+            bar(bucket.records, max)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution, Histogram, bar};
+
+    fn distribution(values: impl IntoIterator<Item = u64>) -> Option<Distribution> {
+        let mut histogram = Histogram::default();
+        for value in values {
+            histogram.record(value);
+        }
+        Distribution::compute(&histogram)
+    }
+
+    #[test]
+    fn test_distribution() {
+        assert!(distribution([]).is_none());
+
+        let dist = distribution((1..=100).rev()).unwrap();
+        assert_eq!(dist.min, 1);
+        assert_eq!(dist.median, 50);
+        assert_eq!(dist.p90, 90);
+        assert_eq!(dist.p99, 99);
+        assert_eq!(dist.max, 100);
+
+        let dist = distribution([7]).unwrap();
+        assert_eq!((dist.min, dist.median, dist.p99, dist.max), (7, 7, 7, 7));
+
+        // Large values are approximate, but min and max stay exact.
+        let dist = distribution((1..=1_000_000).map(|n| n * 3)).unwrap();
+        assert_eq!((dist.min, dist.max), (3, 3_000_000));
+        let within = |value: u64, exact: u64| exact - value <= exact / 128;
+        assert!(within(dist.median, 1_500_000));
+        assert!(within(dist.p90, 2_700_000));
+        assert!(within(dist.p99, 2_970_000));
+    }
+
+    #[test]
+    fn test_histogram_is_bounded() {
+        let mut histogram = Histogram::default();
+        for value in (0..u64::BITS).flat_map(|shift| (0..1000).map(move |n| n << shift)) {
+            histogram.record(value);
+        }
+        assert!(histogram.buckets.len() <= (u64::BITS as usize) << (super::HISTOGRAM_BITS - 1));
+    }
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(0, 0), "");
+        assert_eq!(bar(10, 10).chars().count(), 40);
+        assert_eq!(bar(1, 1000).chars().count(), 1);
+    }
+}
@@ -8,7 +8,7 @@ use s2_sdk::{
         StreamNamePrefix, TimeseriesInterval,
     },
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{OpGroupsParseError, S2UriParseError};
 
@@ -126,19 +126,167 @@ impl FromStr for S2BasinAndStreamUri {
     }
 }
 
-#[derive(Parser, Debug, Clone, Serialize)]
+impl std::fmt::Display for S2BasinAndStreamUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "s2://{}/{}", self.basin, self.stream)
+    }
+}
+
+/// Glob pattern over names, such as of streams, where `*` matches any sequence of
+/// characters (including `/`), `?` matches one character, and `[...]` matches a character class.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: regex::Regex,
+}
+
+impl Glob {
+    const METACHARS: [char; 3] = ['*', '?', '['];
+
+    /// Whether `s` should be treated as a pattern rather than a literal name.
+    pub fn is_pattern(s: &str) -> bool {
+        s.contains(Self::METACHARS)
+    }
+
+    /// Literal prefix shared by all matching names.
+    pub fn prefix(&self) -> &str {
+        let end = self
+            .pattern
+            .find(Self::METACHARS)
+            .unwrap_or(self.pattern.len());
+        &self.pattern[..end]
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut re = String::from("^");
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                '[' => {
+                    let mut j = i + 1;
+                    re.push('[');
+                    if chars.get(j) == Some(&'!') {
+                        re.push('^');
+                        j += 1;
+                    }
+                    // A `]` right after the opening bracket is part of the class.
+                    let start = j;
+                    while j < chars.len() && (chars[j] != ']' || j == start) {
+                        if matches!(chars[j], '\\' | '[' | ']' | '^' | '&' | '~' | '-')
+                            && !(chars[j] == '-' && j != start && chars.get(j + 1) != Some(&']'))
+                        {
+                            re.push('\\');
+                        }
+                        re.push(chars[j]);
+                        j += 1;
+                    }
+                    if j == chars.len() {
+                        return Err(format!("unterminated `[` in pattern `{pattern}`"));
+                    }
+                    re.push(']');
+                    i = j;
+                }
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+            i += 1;
+        }
+        re.push('$');
+        let regex = regex::Regex::new(&re).map_err(|e| e.to_string())?;
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            regex,
+        })
+    }
+}
+
+impl std::fmt::Display for Glob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamSelector {
+    Exact(StreamName),
+    Glob(Glob),
+}
+
+/// String Format: s2://{basin}/{stream}, where the stream may be a glob pattern
+/// such as `tmp/*`.
+#[derive(Debug, Clone)]
+pub struct S2BasinAndStreamSelector {
+    pub basin: BasinName,
+    pub stream: StreamSelector,
+}
+
+impl S2BasinAndStreamSelector {
+    /// The single stream selected, if the URI is not a pattern.
+    pub fn exact(&self) -> Option<S2BasinAndStreamUri> {
+        match &self.stream {
+            StreamSelector::Exact(stream) => Some(S2BasinAndStreamUri {
+                basin: self.basin.clone(),
+                stream: stream.clone(),
+            }),
+            StreamSelector::Glob(_) => None,
+        }
+    }
+}
+
+impl FromStr for S2BasinAndStreamSelector {
+    type Err = S2UriParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let S2Uri { basin, stream } = s.parse()?;
+        let stream = stream.ok_or(S2UriParseError::MissingStreamName)?;
+        let stream = if Glob::is_pattern(&stream) {
+            StreamSelector::Glob(stream.parse().map_err(S2UriParseError::InvalidStreamName)?)
+        } else {
+            StreamSelector::Exact(
+                stream
+                    .parse()
+                    .map_err(|e| S2UriParseError::InvalidStreamName(format!("{e}")))?,
+            )
+        };
+        Ok(Self { basin, stream })
+    }
+}
+
+impl std::fmt::Display for S2BasinAndStreamSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.stream {
+            StreamSelector::Exact(stream) => write!(f, "s2://{}/{stream}", self.basin),
+            StreamSelector::Glob(glob) => write!(f, "s2://{}/{glob}", self.basin),
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
 pub struct BasinConfig {
     #[clap(flatten)]
+    #[serde(default)]
     pub default_stream_config: StreamConfig,
     /// Create stream on append with basin defaults if it doesn't exist.
     #[arg(long, default_value_t = false)]
+    #[serde(default)]
     pub create_stream_on_append: bool,
     /// Create stream on read with basin defaults if it doesn't exist.
     #[arg(long, default_value_t = false)]
+    #[serde(default)]
     pub create_stream_on_read: bool,
 }
 
-#[derive(Parser, Debug, Clone, Serialize, Default)]
+#[derive(Parser, Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct StreamConfig {
     #[arg(long)]
     /// Storage class for a stream.
@@ -154,14 +302,14 @@ pub struct StreamConfig {
     pub delete_on_empty: Option<DeleteOnEmptyConfig>,
 }
 
-#[derive(ValueEnum, Debug, Clone, Serialize)]
+#[derive(ValueEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum StorageClass {
     Standard,
     Express,
 }
 
-#[derive(ValueEnum, Debug, Clone, Serialize)]
+#[derive(ValueEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum TimestampingMode {
     ClientPrefer,
@@ -169,7 +317,7 @@ pub enum TimestampingMode {
     Arrival,
 }
 
-#[derive(Parser, Debug, Clone, Serialize)]
+#[derive(Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TimestampingConfig {
     #[arg(long)]
     /// Timestamping mode.
@@ -180,7 +328,7 @@ pub struct TimestampingConfig {
     pub timestamping_uncapped: Option<bool>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum RetentionPolicy {
     #[allow(dead_code)]
     Age(Duration),
@@ -208,9 +356,59 @@ impl FromStr for RetentionPolicy {
     }
 }
 
-#[derive(Args, Clone, Debug, Serialize)]
+/// Accepts the same strings as the command line, e.g. `"7d"` or `"infinite"`,
+/// as well as the serialized form.
+impl<'de> Deserialize<'de> for RetentionPolicy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        enum Tagged {
+            Age(#[serde(deserialize_with = "deserialize_duration")] Duration),
+            Infinite,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Tagged(Tagged),
+            Human(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Tagged(Tagged::Age(d)) => Ok(RetentionPolicy::Age(d)),
+            Repr::Tagged(Tagged::Infinite) => Ok(RetentionPolicy::Infinite),
+            Repr::Human(s) => {
+                RetentionPolicy::try_from(s.as_str()).map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+/// Deserialize a [`Duration`] from a human-readable string such as `"1d"`,
+/// or from its serialized form.
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Human(String),
+        Serialized(Duration),
+    }
+
+    match Repr::deserialize(deserializer)? {
+        Repr::Human(s) => humantime::parse_duration(&s).map_err(serde::de::Error::custom),
+        Repr::Serialized(d) => Ok(d),
+    }
+}
+
+#[derive(Args, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DeleteOnEmptyConfig {
     #[arg(long, value_parser = humantime::parse_duration, required = false)]
+    #[serde(deserialize_with = "deserialize_duration")]
     /// Minimum age before an empty stream can be deleted.
     /// Example: 1d, 1w, 1y
     pub delete_on_empty_min_age: Duration,
@@ -428,7 +626,7 @@ pub enum AccessTokenMatcher {
     Prefix(AccessTokenIdPrefix),
 }
 
-fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+pub fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
     T: std::fmt::Display,
     S: serde::Serializer,
@@ -695,6 +893,152 @@ impl From<sdk::types::AccessTokenScope> for AccessTokenScope {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct BasinInfo {
+    pub name: String,
+    pub scope: Option<String>,
+    pub state: String,
+}
+
+impl From<sdk::types::BasinInfo> for BasinInfo {
+    fn from(info: sdk::types::BasinInfo) -> Self {
+        BasinInfo {
+            name: info.name.to_string(),
+            scope: info.scope.map(|scope| match scope {
+                sdk::types::BasinScope::AwsUsEast1 => "aws:us-east-1".to_owned(),
+            }),
+            state: match info.state {
+                sdk::types::BasinState::Active => "active",
+                sdk::types::BasinState::Creating => "creating",
+                sdk::types::BasinState::Deleting => "deleting",
+            }
+            .to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamInfo {
+    pub name: String,
+    pub created_at: String,
+    pub deleted_at: Option<String>,
+}
+
+impl From<sdk::types::StreamInfo> for StreamInfo {
+    fn from(info: sdk::types::StreamInfo) -> Self {
+        StreamInfo {
+            name: info.name.to_string(),
+            created_at: info.created_at.to_string(),
+            deleted_at: info.deleted_at.map(|dt| dt.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StreamPosition {
+    pub seq_num: u64,
+    pub timestamp: u64,
+}
+
+impl From<sdk::types::StreamPosition> for StreamPosition {
+    fn from(position: sdk::types::StreamPosition) -> Self {
+        StreamPosition {
+            seq_num: position.seq_num,
+            timestamp: position.timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AppendAck {
+    pub start: StreamPosition,
+    pub end: StreamPosition,
+    pub tail: StreamPosition,
+}
+
+/// Append ack from one shard of a sharded append.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShardAppendAck {
+    pub stream: String,
+    #[serde(flatten)]
+    pub ack: AppendAck,
+}
+
+impl From<sdk::types::AppendAck> for AppendAck {
+    fn from(ack: sdk::types::AppendAck) -> Self {
+        AppendAck {
+            start: ack.start.into(),
+            end: ack.end.into(),
+            tail: ack.tail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Metric {
+    Scalar {
+        name: String,
+        unit: String,
+        value: f64,
+    },
+    Accumulation {
+        name: String,
+        unit: String,
+        interval: String,
+        values: Vec<(u32, f64)>,
+    },
+    Gauge {
+        name: String,
+        unit: String,
+        values: Vec<(u32, f64)>,
+    },
+    Label {
+        name: String,
+        values: Vec<String>,
+    },
+}
+
+fn metric_unit(unit: sdk::types::MetricUnit) -> String {
+    match unit {
+        sdk::types::MetricUnit::Bytes => "bytes",
+        sdk::types::MetricUnit::Operations => "operations",
+    }
+    .to_owned()
+}
+
+impl From<sdk::types::Metric> for Metric {
+    fn from(metric: sdk::types::Metric) -> Self {
+        match metric {
+            sdk::types::Metric::Scalar(m) => Metric::Scalar {
+                name: m.name,
+                unit: metric_unit(m.unit),
+                value: m.value,
+            },
+            sdk::types::Metric::Accumulation(m) => Metric::Accumulation {
+                name: m.name,
+                unit: metric_unit(m.unit),
+                interval: match m.interval {
+                    TimeseriesInterval::Minute => "minute",
+                    TimeseriesInterval::Hour => "hour",
+                    TimeseriesInterval::Day => "day",
+                }
+                .to_owned(),
+                values: m.values,
+            },
+            sdk::types::Metric::Gauge(m) => Metric::Gauge {
+                name: m.name,
+                unit: metric_unit(m.unit),
+                values: m.values,
+            },
+            sdk::types::Metric::Label(m) => Metric::Label {
+                name: m.name,
+                values: m.values,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, clap::ValueEnum, strum::Display, strum::EnumString)]
 #[serde(rename_all = "snake_case")]
 #[clap(rename_all = "snake_case")]
@@ -799,6 +1143,16 @@ impl From<Interval> for TimeseriesInterval {
     }
 }
 
+/// Upper bound on the delay between retries.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Delay before retrying after `attempt` failed attempts, doubling from `initial`.
+pub fn backoff(initial: Duration, attempt: u32) -> Duration {
+    initial
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_BACKOFF)
+}
+
 pub struct LatencyStats {
     pub min: std::time::Duration,
     pub median: std::time::Duration,
@@ -855,9 +1209,12 @@ impl LatencyStats {
 mod tests {
     use crate::error::S2UriParseError;
 
+    use std::time::Duration;
+
     use super::{
-        OpGroupsParseError, PermittedOperationGroups, ReadWritePermissions,
-        S2BasinAndMaybeStreamUri, S2BasinAndStreamUri, S2BasinUri, S2Uri,
+        Glob, MAX_BACKOFF, OpGroupsParseError, PermittedOperationGroups, ReadWritePermissions,
+        S2BasinAndMaybeStreamUri, S2BasinAndStreamSelector, S2BasinAndStreamUri, S2BasinUri, S2Uri,
+        StreamSelector, backoff,
     };
     use rstest::rstest;
 
@@ -1045,4 +1402,62 @@ mod tests {
             );
         }
     }
+
+    #[rstest]
+    #[case("tmp/*", "tmp/", &["tmp/a", "tmp/a/b", "tmp/"], &["tmp", "xtmp/a"])]
+    #[case("logs-??", "logs-", &["logs-01"], &["logs-1", "logs-001"])]
+    #[case("a[0-9]", "a", &["a0", "a9"], &["ab", "a-"])]
+    #[case("a[!0-9]", "a", &["ab", "a-"], &["a0"])]
+    #[case("a[-.]", "a", &["a-", "a."], &["a0"])]
+    #[case("a.b*", "a.b", &["a.b", "a.bc"], &["axb"])]
+    fn test_glob(
+        #[case] pattern: &str,
+        #[case] prefix: &str,
+        #[case] matching: &[&str],
+        #[case] non_matching: &[&str],
+    ) {
+        let glob: Glob = pattern.parse().unwrap();
+        assert_eq!(glob.prefix(), prefix);
+        for name in matching {
+            assert!(glob.matches(name), "{pattern} should match {name}");
+        }
+        for name in non_matching {
+            assert!(!glob.matches(name), "{pattern} should not match {name}");
+        }
+    }
+
+    #[test]
+    fn test_stream_selector_parse() {
+        let selector: S2BasinAndStreamSelector = "s2://my-basin-1/tmp/*".parse().unwrap();
+        assert!(matches!(selector.stream, StreamSelector::Glob(_)));
+        assert!(selector.exact().is_none());
+        assert_eq!(selector.to_string(), "s2://my-basin-1/tmp/*");
+
+        let selector: S2BasinAndStreamSelector = "s2://my-basin-1/tmp/a".parse().unwrap();
+        assert_eq!(
+            selector.exact().unwrap(),
+            "s2://my-basin-1/tmp/a"
+                .parse::<S2BasinAndStreamUri>()
+                .unwrap()
+        );
+
+        assert!(
+            "s2://my-basin-1/tmp/[a"
+                .parse::<S2BasinAndStreamSelector>()
+                .is_err()
+        );
+        assert!(
+            "s2://my-basin-1"
+                .parse::<S2BasinAndStreamSelector>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let initial = Duration::from_millis(500);
+        assert_eq!(backoff(initial, 1), initial);
+        assert_eq!(backoff(initial, 3), Duration::from_secs(2));
+        assert_eq!(backoff(initial, 100), MAX_BACKOFF);
+    }
 }
@@ -0,0 +1,369 @@
+//! Delivering records read to an HTTP endpoint, for `read --webhook`.
+//!
+//! Each batch read is POSTed as a JSON array of records. Up to the configured
+//! number of batches are in flight at once, but deliveries are acknowledged in
+//! read order, so a checkpoint never skips past an undelivered batch.
+
+use std::{error::Error, str::FromStr};
+
+use bytes::Bytes;
+use colored::Colorize;
+use futures::{Stream, StreamExt};
+use http::{HeaderName, HeaderValue, StatusCode};
+use s2_sdk::{S2, types::SequencedRecord};
+use tokio::select;
+
+use crate::{
+    checkpoint::Checkpoint,
+    cli::{ReadArgs, WebhookArgs},
+    error::{CliError, OpKind},
+    filter::RecordFilter,
+    ops,
+    record_format::{JsonBase64Formatter, JsonFormatter, RecordFormat},
+    types::{S2BasinAndStreamUri, backoff},
+};
+
+/// Header added to webhook requests, of the form `name: value`.
+#[derive(Debug, Clone)]
+pub struct WebhookHeader(HeaderName, HeaderValue);
+
+impl FromStr for WebhookHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected `name: value`, got `{s}`"))?;
+        Ok(Self(
+            name.trim().parse().map_err(|e| format!("{e}"))?,
+            value.trim().parse().map_err(|e| format!("{e}"))?,
+        ))
+    }
+}
+
+struct Webhook {
+    client: reqwest::Client,
+    args: WebhookArgs,
+    url: reqwest::Url,
+    stream: S2BasinAndStreamUri,
+}
+
+/// A serialized batch to deliver.
+struct Delivery {
+    /// Sequence number of the last record read in the batch.
+    last: Option<u64>,
+    /// Records to deliver after filtering.
+    count: usize,
+    describe: String,
+    body: Bytes,
+}
+
+impl Webhook {
+    /// Deliver batches with up to `webhook_concurrency` in flight, yielding the
+    /// last sequence number and record count of each in the order given.
+    fn deliver_all<'a>(
+        &'a self,
+        deliveries: impl Stream<Item = Result<Delivery, CliError>> + 'a,
+    ) -> impl Stream<Item = Result<(Option<u64>, usize), CliError>> + 'a {
+        deliveries
+            .map(move |delivery| async move {
+                let delivery = delivery?;
+                if delivery.count > 0 {
+                    self.deliver(delivery.body, &delivery.describe).await?;
+                }
+                Ok((delivery.last, delivery.count))
+            })
+            .buffered(self.args.webhook_concurrency.get())
+    }
+
+    /// POST a batch, retrying transient failures.
+    async fn deliver(&self, body: Bytes, describe: &str) -> Result<(), CliError> {
+        let mut attempt = 1;
+        loop {
+            let mut request = self
+                .client
+                .post(self.url.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header("s2-stream", self.stream.to_string())
+                .body(body.clone());
+            for WebhookHeader(name, value) in &self.args.webhook_headers {
+                request = request.header(name, value);
+            }
+            let reason = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    response.status().to_string()
+                }
+                Ok(response) => {
+                    return Err(CliError::Webhook(
+                        describe.to_owned(),
+                        response.status().to_string(),
+                    ));
+                }
+                Err(e) => std::iter::successors(Some(&e as &dyn Error), |e| (*e).source())
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(": "),
+            };
+            if attempt >= self.args.webhook_attempts.get() {
+                return Err(CliError::Webhook(describe.to_owned(), reason));
+            }
+            let delay = backoff(*self.args.webhook_backoff, attempt);
+            eprintln!(
+                "{}",
+                format!(
+                    "↻ [RETRYING] {describe} in {}: {reason}",
+                    humantime::format_duration(delay)
+                )
+                .yellow()
+                .bold()
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Read `uri` and deliver its records to the webhook, checkpointing after each batch.
+pub async fn run(
+    s2: &S2,
+    uri: S2BasinAndStreamUri,
+    url: reqwest::Url,
+    args: &ReadArgs,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<(), CliError> {
+    let client = reqwest::Client::builder()
+        .timeout(*args.webhook.webhook_timeout)
+        .build()
+        .map_err(|e| CliError::Webhook(url.to_string(), e.to_string()))?;
+    let webhook = Webhook {
+        client,
+        args: args.webhook.clone(),
+        url,
+        stream: uri.clone(),
+    };
+    let serialize = |records: Vec<&SequencedRecord>| match args.format {
+        RecordFormat::JsonBase64 => JsonBase64Formatter {}.serialize_batch(records),
+        // Other formats are rejected with `--webhook`.
+        _ => JsonFormatter {}.serialize_batch(records),
+    };
+
+    let mut filter = RecordFilter::new(&args.filter);
+    let mut delivered = 0;
+    let batches = ops::read(s2, uri, args).await?;
+    let deliveries = batches.map(|batch| {
+        let batch = batch.map_err(|e| CliError::op(OpKind::Read, e))?;
+        let records: Vec<_> = batch
+            .records
+            .iter()
+            .filter(|record| !args.commands.only_commands || record.is_command_record())
+            .filter(|record| filter.as_mut().is_none_or(|f| f.matches(record)))
+            .collect();
+        let last = batch.records.last().map(|record| record.seq_num);
+        let count = records.len();
+        let describe = match (records.first(), records.last()) {
+            (Some(first), Some(last)) => {
+                format!("seq_nums {}..={}", first.seq_num, last.seq_num)
+            }
+            _ => String::new(),
+        };
+        let body = serialize(records).map_err(|e| CliError::RecordWrite(e.to_string()))?;
+        Ok(Delivery {
+            last,
+            count,
+            describe,
+            body: body.into(),
+        })
+    });
+    let mut deliveries = Box::pin(webhook.deliver_all(deliveries));
+
+    loop {
+        select! {
+            delivery = deliveries.next() => match delivery {
+                Some(Ok((last, count))) => {
+                    delivered += count;
+                    if let (Some(checkpoint), Some(last)) = (checkpoint, last) {
+                        checkpoint.save(last)?;
+                    }
+                }
+                Some(Err(e)) => return Err(e),
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{}", "■ [ABORTED]".red().bold());
+                break;
+            }
+        }
+    }
+    drop(deliveries);
+
+    eprintln!(
+        "{}",
+        format!("✓ [DELIVERED] {delivered} records").green().bold()
+    );
+    if let Some(filter) = &filter {
+        filter.report();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{Router, extract::State, http::StatusCode, routing::post};
+    use bytes::Bytes;
+    use futures::{TryStreamExt, stream};
+
+    use super::{Delivery, Webhook, WebhookHeader};
+    use crate::cli::WebhookArgs;
+
+    /// Serve the webhook endpoint in-process, answering each request with the
+    /// next of `statuses` (then 200), and recording the bodies received.
+    async fn serve(statuses: Vec<StatusCode>) -> (reqwest::Url, Arc<Mutex<Vec<Bytes>>>) {
+        #[derive(Clone)]
+        struct Endpoint {
+            statuses: Arc<Mutex<std::vec::IntoIter<StatusCode>>>,
+            received: Arc<Mutex<Vec<Bytes>>>,
+        }
+
+        async fn handle(State(endpoint): State<Endpoint>, body: Bytes) -> StatusCode {
+            // Batches whose body asks to be slow are delivered after later ones.
+            if body.starts_with(b"slow") {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            endpoint.received.lock().expect("not poisoned").push(body);
+            let next = endpoint.statuses.lock().expect("not poisoned").next();
+            next.unwrap_or(StatusCode::OK)
+        }
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let endpoint = Endpoint {
+            statuses: Arc::new(Mutex::new(statuses.into_iter())),
+            received: received.clone(),
+        };
+        let app = Router::new()
+            .route("/hook", post(handle))
+            .with_state(endpoint);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let url = format!("http://{addr}/hook").parse().unwrap();
+        (url, received)
+    }
+
+    fn webhook(url: reqwest::Url, concurrency: usize) -> Webhook {
+        Webhook {
+            client: reqwest::Client::builder().no_proxy().build().unwrap(),
+            args: WebhookArgs {
+                webhook: Some(url.clone()),
+                webhook_headers: Vec::new(),
+                webhook_concurrency: concurrency.try_into().unwrap(),
+                webhook_attempts: 3.try_into().unwrap(),
+                webhook_backoff: Duration::from_millis(1).into(),
+                webhook_timeout: Duration::from_secs(5).into(),
+            },
+            url,
+            stream: "s2://my-basin/events".parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_success() {
+        let (url, received) = serve(vec![StatusCode::NO_CONTENT]).await;
+        webhook(url, 1)
+            .deliver(Bytes::from_static(b"[1]"), "batch")
+            .await
+            .unwrap();
+        assert_eq!(*received.lock().unwrap(), vec![Bytes::from_static(b"[1]")]);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries_server_errors() {
+        let (url, received) = serve(vec![
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ])
+        .await;
+        webhook(url, 1)
+            .deliver(Bytes::from_static(b"[1]"), "batch")
+            .await
+            .unwrap();
+        assert_eq!(received.lock().unwrap().len(), 3);
+
+        // Giving up after the last attempt.
+        let (url, received) = serve(vec![StatusCode::BAD_GATEWAY; 3]).await;
+        assert!(
+            webhook(url, 1)
+                .deliver(Bytes::from_static(b"[1]"), "batch")
+                .await
+                .is_err()
+        );
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_aborts_on_client_error() {
+        let (url, received) = serve(vec![StatusCode::BAD_REQUEST]).await;
+        assert!(
+            webhook(url, 1)
+                .deliver(Bytes::from_static(b"[1]"), "batch")
+                .await
+                .is_err()
+        );
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_all_acks_in_order() {
+        let (url, received) = serve(Vec::new()).await;
+        let webhook = webhook(url, 3);
+        let delivery = |last: u64, body: &'static str| {
+            Ok(Delivery {
+                last: Some(last),
+                count: 1,
+                describe: format!("seq_nums {last}..={last}"),
+                body: Bytes::from_static(body.as_bytes()),
+            })
+        };
+        let deliveries = stream::iter([
+            delivery(0, "slow 0"),
+            delivery(1, "1"),
+            // Nothing left after filtering, so nothing to deliver.
+            Ok(Delivery {
+                last: Some(2),
+                count: 0,
+                describe: String::new(),
+                body: Bytes::new(),
+            }),
+            delivery(3, "3"),
+        ]);
+        let acks: Vec<_> = webhook
+            .deliver_all(deliveries)
+            .map_ok(|(last, _)| last)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(acks, vec![Some(0), Some(1), Some(2), Some(3)]);
+        // The slow batch arrived after the next one, but was still acknowledged first.
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0], Bytes::from_static(b"1"));
+        assert_eq!(received[1], Bytes::from_static(b"slow 0"));
+    }
+
+    #[test]
+    fn test_webhook_header() {
+        let WebhookHeader(name, value) = "Authorization: Bearer abc".parse().unwrap();
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer abc");
+        assert!("Authorization".parse::<WebhookHeader>().is_err());
+        assert!("bad name: x".parse::<WebhookHeader>().is_err());
+    }
+}
@@ -77,3 +77,417 @@ fn config_set_invalid_key() {
         .assert()
         .failure();
 }
+
+#[test]
+fn config_profiles() {
+    let home = tempfile::TempDir::new().unwrap();
+    let s2 = || {
+        let mut cmd = s2();
+        cmd.env("HOME", home.path()).env_remove("S2_PROFILE");
+        cmd
+    };
+
+    s2().args(["config", "set", "compression", "gzip"])
+        .assert()
+        .success();
+    s2().args(["config", "create-profile", "staging"])
+        .assert()
+        .success();
+    s2().args([
+        "--profile",
+        "staging",
+        "config",
+        "set",
+        "compression",
+        "zstd",
+    ])
+    .assert()
+    .success();
+
+    s2().args(["config", "get", "compression"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("gzip"));
+    s2().args(["--profile", "staging", "config", "get", "compression"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("zstd"));
+
+    s2().args(["config", "switch-profile", "staging"])
+        .assert()
+        .success();
+    s2().args(["config", "get", "compression"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("zstd"));
+    s2().args(["config", "list-profiles"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("default").and(predicate::str::contains("staging")));
+
+    s2().args(["config", "delete-profile", "staging"])
+        .assert()
+        .success();
+    s2().args(["config", "get", "compression"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("gzip"));
+}
+
+#[test]
+fn config_profile_not_found() {
+    let home = tempfile::TempDir::new().unwrap();
+    s2().env("HOME", home.path())
+        .args(["--profile", "missing", "config", "get", "compression"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+    s2().env("HOME", home.path())
+        .args(["config", "delete-profile", "default"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn access_token_command_failure() {
+    let home = tempfile::TempDir::new().unwrap();
+    s2().env("HOME", home.path())
+        .args(["config", "set", "access_token_command", "exit 3"])
+        .assert()
+        .success();
+    s2().env("HOME", home.path())
+        .env_remove("S2_ACCESS_TOKEN")
+        .args(["list-basins"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Access token command failed"));
+}
+
+#[test]
+fn config_list_json_output() {
+    let home = tempfile::TempDir::new().unwrap();
+    s2().env("HOME", home.path())
+        .args(["config", "set", "compression", "zstd"])
+        .assert()
+        .success();
+    s2().env("HOME", home.path())
+        .args(["--output-format", "ndjson", "config", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("{\"compression\":\"zstd\"}\n"));
+}
+
+#[test]
+fn read_args_parse_with_global_output() {
+    let mut cmd = s2();
+    cmd.env_remove("S2_ACCESS_TOKEN");
+    cmd.args([
+        "--output-format",
+        "json",
+        "read",
+        "s2://my-basin-1/stream",
+        "--output",
+        "-",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("access token"));
+}
+
+#[test]
+fn invalid_output_format() {
+    s2().args(["--output-format", "xml", "config", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn invalid_read_template() {
+    s2().args([
+        "read",
+        "s2://my-basin-1/stream",
+        "--template",
+        "{seq_num:hex}",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn append_resume_state_requires_file() {
+    let home = tempfile::TempDir::new().unwrap();
+    s2().env("HOME", home.path())
+        .env("S2_ACCESS_TOKEN", "test-token")
+        .args([
+            "append",
+            "s2://my-basin-1/stream",
+            "--resume-state",
+            home.path().join("state").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("requires a file input"));
+}
+
+#[test]
+fn cp_missing_destination() {
+    s2().args(["cp", "s2://my-basin-1/source"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("DST_URI"));
+}
+
+#[test]
+fn import_invalid_archive() {
+    let home = tempfile::TempDir::new().unwrap();
+    let archive = home.path().join("backup.s2a");
+    std::fs::write(&archive, b"definitely not an archive").unwrap();
+    s2().env("HOME", home.path())
+        .env("S2_ACCESS_TOKEN", "test-token")
+        .args([
+            "import",
+            archive.to_str().unwrap(),
+            "s2://my-basin-1/restored",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not an S2 archive"));
+}
+
+#[test]
+fn plan_invalid_manifest() {
+    let home = tempfile::TempDir::new().unwrap();
+    let manifest = home.path().join("s2.toml");
+    std::fs::write(&manifest, "[basins.-invalid]\n").unwrap();
+    s2().env("HOME", home.path())
+        .env("S2_ACCESS_TOKEN", "test-token")
+        .args(["plan", "-f", manifest.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid basin name"));
+}
+
+#[test]
+fn delete_stream_invalid_glob() {
+    s2().args(["delete-stream", "s2://my-basin-1/tmp/[a", "--yes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unterminated"));
+}
+
+#[test]
+fn trim_requires_single_target() {
+    s2().args(["trim", "s2://my-basin-1/stream"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--older-than"));
+    s2().args(["trim", "s2://my-basin-1/stream", "5", "--keep-last", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn lock_run_requires_command() {
+    s2().args(["lock", "run", "s2://my-basin-1/locks/job", "--holder", "me"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("<COMMAND>..."));
+    s2().args(["lock", "acquire", "s2://my-basin-1/locks/job"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--holder"));
+}
+
+#[test]
+fn lock_rejects_sub_second_ttl() {
+    s2().args([
+        "lock",
+        "run",
+        "s2://my-basin-1/locks/job",
+        "--holder",
+        "me",
+        "--ttl",
+        "0s",
+        "--",
+        "true",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("must be at least 1s"));
+}
+
+#[test]
+fn read_command_filters_conflict() {
+    s2().args([
+        "read",
+        "s2://my-basin-1/stream",
+        "--skip-commands",
+        "--only-commands",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn read_invalid_header_filter() {
+    s2().args(["read", "s2://my-basin-1/stream", "--header-filter", "level"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("name=glob"));
+    s2().args(["tail", "s2://my-basin-1/stream", "--body-grep", "("])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--body-grep"));
+}
+
+#[test]
+fn stats_single_start_position() {
+    s2().args(["stats", "s2://my-basin-1/stream", "--ago", "1h", "-s", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn read_checkpoint_requires_single_stream() {
+    let home = tempfile::TempDir::new().unwrap();
+    s2().env("HOME", home.path())
+        .env("S2_ACCESS_TOKEN", "test-token")
+        .args([
+            "read",
+            "s2://my-basin-1/events/0",
+            "s2://my-basin-1/events/1",
+            "--checkpoint",
+            home.path().join("ckpt").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--checkpoint requires a single stream",
+        ));
+}
+
+#[test]
+fn append_shard_by_conflicts_with_match_seq_num() {
+    s2().args([
+        "append",
+        "s2://my-basin-1/events/{0..3}",
+        "--shard-by",
+        "header:tenant",
+        "--match-seq-num",
+        "0",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn append_invalid_shard_key() {
+    s2().args([
+        "append",
+        "s2://my-basin-1/events/{0..3}",
+        "--shard-by",
+        "tenant",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("header:NAME"));
+}
+
+#[test]
+fn read_exec_requires_command() {
+    s2().args(["read", "s2://my-basin-1/stream", "--exec"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("COMMAND"));
+}
+
+#[test]
+fn read_exec_options_require_exec() {
+    s2().args([
+        "read",
+        "s2://my-basin-1/stream",
+        "--exec-mode",
+        "batch",
+        "--",
+        "cat",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("--exec"));
+}
+
+#[test]
+fn read_webhook_conflicts_with_exec() {
+    s2().args([
+        "read",
+        "s2://my-basin-1/stream",
+        "--webhook",
+        "http://localhost:8080/ingest",
+        "--exec",
+        "--",
+        "cat",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn read_webhook_requires_json_format() {
+    for format in [&["--format", "csv"][..], &[], &["--template", "{body}"]] {
+        let home = tempfile::TempDir::new().unwrap();
+        s2().env("HOME", home.path())
+            .env("S2_ACCESS_TOKEN", "test-token")
+            .args([
+                "read",
+                "s2://my-basin-1/stream",
+                "--webhook",
+                "http://localhost:8080/ingest",
+            ])
+            .args(format)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "--webhook requires --format json or json-base64",
+            ));
+    }
+}
+
+#[test]
+fn read_invalid_webhook_header() {
+    s2().args([
+        "read",
+        "s2://my-basin-1/stream",
+        "--webhook",
+        "http://localhost:8080/ingest",
+        "--webhook-header",
+        "Authorization",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("name: value"));
+}
+
+#[test]
+fn serve_invalid_listen_address() {
+    s2().args(["serve", "--listen", "localhost"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value"));
+}
+
+#[test]
+fn serve_non_loopback_requires_token() {
+    s2().env("S2_ACCESS_TOKEN", "test-token")
+        .env_remove("S2_SERVE_TOKEN")
+        .args(["serve", "--listen", "0.0.0.0:0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("without --token"));
+}